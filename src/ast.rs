@@ -0,0 +1,538 @@
+use crate::expr::Expr;
+use crate::json::Json;
+use crate::scanner::{self, Token, TokenType};
+use crate::stmt::Stmt;
+
+/// Serializes a program to JSON. Every node is an object whose `kind` names
+/// the `Stmt` or `Expr` variant and whose other members are the variant's
+/// fields under their Rust names. Tokens keep their type, lexeme, literal,
+/// line and column, so [`from_json`] rebuilds an identical tree.
+pub fn to_json(stmts: &[Stmt]) -> Json {
+    Json::object(vec![(
+        "statements",
+        stmts.iter().map(stmt_to_json).collect::<Vec<Json>>().into(),
+    )])
+}
+
+/// Rebuilds a program serialized by [`to_json`].
+pub fn from_json(json: &Json) -> Result<Vec<Stmt>, String> {
+    list(json, "statements", "program")?
+        .iter()
+        .map(stmt_from_json)
+        .collect()
+}
+
+fn token_to_json(token: &Token) -> Json {
+    let literal = match &token.literal {
+        Some(scanner::LiteralValue::FValue(x)) => Json::Number(*x),
+        Some(scanner::LiteralValue::StringValue(s)) => Json::String(s.clone()),
+        None => Json::Null,
+    };
+    Json::object(vec![
+        ("type", token.token_type.to_string().into()),
+        ("lexeme", token.lexeme.as_str().into()),
+        ("literal", literal),
+        ("line", token.lineNumber.into()),
+        ("column", token.column.into()),
+    ])
+}
+
+fn tokens_to_json(tokens: &[Token]) -> Json {
+    tokens.iter().map(token_to_json).collect::<Vec<Json>>().into()
+}
+
+fn optional_token_to_json(token: &Option<Token>) -> Json {
+    token.as_ref().map(token_to_json).into()
+}
+
+fn stmts_to_json(stmts: &[Box<Stmt>]) -> Json {
+    stmts
+        .iter()
+        .map(|stmt| stmt_to_json(stmt))
+        .collect::<Vec<Json>>()
+        .into()
+}
+
+pub(crate) fn stmt_to_json(stmt: &Stmt) -> Json {
+    let (kind, mut fields): (&str, Vec<(&str, Json)>) = match stmt {
+        Stmt::Expression { expression } => {
+            ("Expression", vec![("expression", expr_to_json(expression))])
+        }
+        Stmt::Print {
+            keyword,
+            expression,
+        } => (
+            "Print",
+            vec![
+                ("keyword", token_to_json(keyword)),
+                ("expression", expr_to_json(expression)),
+            ],
+        ),
+        Stmt::Var {
+            name,
+            type_annotation,
+            initializer,
+        } => (
+            "Var",
+            vec![
+                ("name", token_to_json(name)),
+                ("type_annotation", optional_token_to_json(type_annotation)),
+                ("initializer", expr_to_json(initializer)),
+            ],
+        ),
+        Stmt::Block { statements } => ("Block", vec![("statements", stmts_to_json(statements))]),
+        Stmt::IfStmt {
+            keyword,
+            predicate,
+            then,
+            els,
+        } => (
+            "IfStmt",
+            vec![
+                ("keyword", token_to_json(keyword)),
+                ("predicate", expr_to_json(predicate)),
+                ("then", stmt_to_json(then)),
+                ("els", els.as_ref().map(|els| stmt_to_json(els)).into()),
+            ],
+        ),
+        Stmt::WhileStmt {
+            keyword,
+            condition,
+            body,
+        } => (
+            "WhileStmt",
+            vec![
+                ("keyword", token_to_json(keyword)),
+                ("condition", expr_to_json(condition)),
+                ("body", stmt_to_json(body)),
+            ],
+        ),
+        Stmt::ForIn {
+            keyword,
+            variable,
+            iterable,
+            body,
+        } => (
+            "ForIn",
+            vec![
+                ("keyword", token_to_json(keyword)),
+                ("variable", token_to_json(variable)),
+                ("iterable", expr_to_json(iterable)),
+                ("body", stmt_to_json(body)),
+            ],
+        ),
+        Stmt::Function {
+            name,
+            params,
+            param_types,
+            defaults,
+            rest,
+            return_type,
+            body,
+        } => (
+            "Function",
+            vec![
+                ("name", token_to_json(name)),
+                ("params", tokens_to_json(params)),
+                (
+                    "param_types",
+                    param_types
+                        .iter()
+                        .map(optional_token_to_json)
+                        .collect::<Vec<Json>>()
+                        .into(),
+                ),
+                ("defaults", defaults_to_json(defaults)),
+                ("rest", (*rest).into()),
+                ("return_type", optional_token_to_json(return_type)),
+                ("body", stmts_to_json(body)),
+            ],
+        ),
+        Stmt::ReturnStmt { keyword, value } => (
+            "ReturnStmt",
+            vec![
+                ("keyword", token_to_json(keyword)),
+                ("value", value.as_ref().map(expr_to_json).into()),
+            ],
+        ),
+    };
+    fields.insert(0, ("kind", kind.into()));
+    Json::object(fields)
+}
+
+fn defaults_to_json(defaults: &[Option<Expr>]) -> Json {
+    defaults
+        .iter()
+        .map(|default| default.as_ref().map(expr_to_json).into())
+        .collect::<Vec<Json>>()
+        .into()
+}
+
+fn expr_to_json(expr: &Expr) -> Json {
+    let (kind, mut fields): (&str, Vec<(&str, Json)>) = match expr {
+        Expr::AnonFunction {
+            paren,
+            arguments,
+            param_types,
+            defaults,
+            rest,
+            return_type,
+            body,
+        } => (
+            "AnonFunction",
+            vec![
+                ("paren", token_to_json(paren)),
+                ("arguments", tokens_to_json(arguments)),
+                (
+                    "param_types",
+                    param_types
+                        .iter()
+                        .map(optional_token_to_json)
+                        .collect::<Vec<Json>>()
+                        .into(),
+                ),
+                ("defaults", defaults_to_json(defaults)),
+                ("rest", (*rest).into()),
+                ("return_type", optional_token_to_json(return_type)),
+                ("body", stmts_to_json(body)),
+            ],
+        ),
+        Expr::Assign { name, value } => (
+            "Assign",
+            vec![("name", token_to_json(name)), ("value", expr_to_json(value))],
+        ),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => (
+            "Binary",
+            vec![
+                ("left", expr_to_json(left)),
+                ("operator", token_to_json(operator)),
+                ("right", expr_to_json(right)),
+            ],
+        ),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+            named,
+        } => (
+            "Call",
+            vec![
+                ("callee", expr_to_json(callee)),
+                ("paren", token_to_json(paren)),
+                (
+                    "arguments",
+                    arguments.iter().map(expr_to_json).collect::<Vec<Json>>().into(),
+                ),
+                (
+                    "named",
+                    named
+                        .iter()
+                        .map(|(name, value)| {
+                            Json::object(vec![
+                                ("name", token_to_json(name)),
+                                ("value", expr_to_json(value)),
+                            ])
+                        })
+                        .collect::<Vec<Json>>()
+                        .into(),
+                ),
+            ],
+        ),
+        Expr::Grouping { expression } => ("Grouping", vec![("expression", expr_to_json(expression))]),
+        // Literals in a program are numbers, strings, booleans or nil, which
+        // all have a JSON form.
+        Expr::Literal { value } => (
+            "Literal",
+            vec![("value", Json::from_value(value).unwrap_or(Json::Null))],
+        ),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => (
+            "Logical",
+            vec![
+                ("left", expr_to_json(left)),
+                ("operator", token_to_json(operator)),
+                ("right", expr_to_json(right)),
+            ],
+        ),
+        Expr::Unary { operator, right } => (
+            "Unary",
+            vec![("operator", token_to_json(operator)), ("right", expr_to_json(right))],
+        ),
+        Expr::Variable { name } => ("Variable", vec![("name", token_to_json(name))]),
+    };
+    fields.insert(0, ("kind", kind.into()));
+    Json::object(fields)
+}
+
+fn field<'a>(json: &'a Json, key: &str, kind: &str) -> Result<&'a Json, String> {
+    json.get(key)
+        .ok_or_else(|| format!("{} node is missing '{}'", kind, key))
+}
+
+fn list<'a>(json: &'a Json, key: &str, kind: &str) -> Result<&'a Vec<Json>, String> {
+    field(json, key, kind)?
+        .as_array()
+        .ok_or_else(|| format!("'{}' of {} node must be an array", key, kind))
+}
+
+fn token_from_json(json: &Json) -> Result<Token, String> {
+    let token_type: TokenType = field(json, "type", "Token")?
+        .as_str()
+        .ok_or("Token type must be a string")?
+        .parse()?;
+    let lexeme = field(json, "lexeme", "Token")?
+        .as_str()
+        .ok_or("Token lexeme must be a string")?
+        .to_string();
+    let literal = match json.get("literal") {
+        Some(Json::Number(x)) => Some(scanner::LiteralValue::FValue(*x)),
+        Some(Json::String(s)) => Some(scanner::LiteralValue::StringValue(s.clone())),
+        _ => None,
+    };
+    let line = field(json, "line", "Token")?
+        .as_usize()
+        .ok_or("Token line must be a whole number")?;
+    let column = json.get("column").and_then(|c| c.as_usize()).unwrap_or(0);
+    Ok(Token::new(token_type, lexeme, literal, line, column))
+}
+
+fn tokens_from_json(json: &Json, key: &str, kind: &str) -> Result<Vec<Token>, String> {
+    list(json, key, kind)?.iter().map(token_from_json).collect()
+}
+
+fn optional_token_from_json(json: Option<&Json>) -> Result<Option<Token>, String> {
+    match json {
+        None | Some(Json::Null) => Ok(None),
+        Some(token) => token_from_json(token).map(Some),
+    }
+}
+
+fn stmts_from_json(json: &Json, key: &str, kind: &str) -> Result<Vec<Box<Stmt>>, String> {
+    list(json, key, kind)?
+        .iter()
+        .map(|stmt| stmt_from_json(stmt).map(Box::new))
+        .collect()
+}
+
+fn param_types_from_json(json: &Json, kind: &str) -> Result<Vec<Option<Token>>, String> {
+    match json.get("param_types") {
+        None => Ok(vec![]),
+        Some(_) => list(json, "param_types", kind)?
+            .iter()
+            .map(|t| optional_token_from_json(Some(t)))
+            .collect(),
+    }
+}
+
+/// Reads the `defaults` of a function node, which older trees don't have.
+fn defaults_from_json(json: &Json, kind: &str, count: usize) -> Result<Vec<Option<Expr>>, String> {
+    let mut defaults = match json.get("defaults") {
+        None => vec![],
+        Some(_) => list(json, "defaults", kind)?
+            .iter()
+            .map(|default| match default {
+                Json::Null => Ok(None),
+                default => expr_from_json(default).map(Some),
+            })
+            .collect::<Result<Vec<Option<Expr>>, String>>()?,
+    };
+    defaults.resize_with(count, || None);
+    Ok(defaults)
+}
+
+fn kind_of(json: &Json) -> Result<&str, String> {
+    json.get("kind")
+        .and_then(|kind| kind.as_str())
+        .ok_or_else(|| "AST node is missing its 'kind'".to_string())
+}
+
+pub(crate) fn stmt_from_json(json: &Json) -> Result<Stmt, String> {
+    let kind = kind_of(json)?;
+    let token = |key: &str| token_from_json(field(json, key, kind)?);
+    let expr = |key: &str| expr_from_json(field(json, key, kind)?);
+    let stmt = |key: &str| stmt_from_json(field(json, key, kind)?).map(Box::new);
+
+    Ok(match kind {
+        "Expression" => Stmt::Expression {
+            expression: expr("expression")?,
+        },
+        "Print" => Stmt::Print {
+            keyword: token("keyword")?,
+            expression: expr("expression")?,
+        },
+        "Var" => Stmt::Var {
+            name: token("name")?,
+            type_annotation: optional_token_from_json(json.get("type_annotation"))?,
+            initializer: expr("initializer")?,
+        },
+        "Block" => Stmt::Block {
+            statements: stmts_from_json(json, "statements", kind)?,
+        },
+        "IfStmt" => Stmt::IfStmt {
+            keyword: token("keyword")?,
+            predicate: expr("predicate")?,
+            then: stmt("then")?,
+            els: match json.get("els") {
+                None | Some(Json::Null) => None,
+                Some(els) => Some(Box::new(stmt_from_json(els)?)),
+            },
+        },
+        "WhileStmt" => Stmt::WhileStmt {
+            keyword: token("keyword")?,
+            condition: expr("condition")?,
+            body: stmt("body")?,
+        },
+        "ForIn" => Stmt::ForIn {
+            keyword: token("keyword")?,
+            variable: token("variable")?,
+            iterable: expr("iterable")?,
+            body: stmt("body")?,
+        },
+        "Function" => {
+            let params = tokens_from_json(json, "params", kind)?;
+            let mut param_types = param_types_from_json(json, kind)?;
+            param_types.resize(params.len(), None);
+            Stmt::Function {
+                name: token("name")?,
+                defaults: defaults_from_json(json, kind, params.len())?,
+                rest: json.get("rest").and_then(Json::as_bool).unwrap_or(false),
+                params,
+                param_types,
+                return_type: optional_token_from_json(json.get("return_type"))?,
+                body: stmts_from_json(json, "body", kind)?,
+            }
+        }
+        "ReturnStmt" => Stmt::ReturnStmt {
+            keyword: token("keyword")?,
+            value: match json.get("value") {
+                None | Some(Json::Null) => None,
+                Some(value) => Some(expr_from_json(value)?),
+            },
+        },
+        other => return Err(format!("Unknown statement kind '{}'", other)),
+    })
+}
+
+fn expr_from_json(json: &Json) -> Result<Expr, String> {
+    let kind = kind_of(json)?;
+    let token = |key: &str| token_from_json(field(json, key, kind)?);
+    let expr = |key: &str| expr_from_json(field(json, key, kind)?).map(Box::new);
+
+    Ok(match kind {
+        "AnonFunction" => {
+            let arguments = tokens_from_json(json, "arguments", kind)?;
+            let mut param_types = param_types_from_json(json, kind)?;
+            param_types.resize(arguments.len(), None);
+            Expr::AnonFunction {
+                paren: token("paren")?,
+                defaults: defaults_from_json(json, kind, arguments.len())?,
+                rest: json.get("rest").and_then(Json::as_bool).unwrap_or(false),
+                arguments,
+                param_types,
+                return_type: optional_token_from_json(json.get("return_type"))?,
+                body: stmts_from_json(json, "body", kind)?,
+            }
+        }
+        "Assign" => Expr::Assign {
+            name: token("name")?,
+            value: expr("value")?,
+        },
+        "Binary" => Expr::Binary {
+            left: expr("left")?,
+            operator: token("operator")?,
+            right: expr("right")?,
+        },
+        "Call" => Expr::Call {
+            callee: expr("callee")?,
+            paren: token("paren")?,
+            arguments: list(json, "arguments", kind)?
+                .iter()
+                .map(expr_from_json)
+                .collect::<Result<Vec<Expr>, String>>()?,
+            named: match json.get("named") {
+                None => vec![],
+                Some(_) => list(json, "named", kind)?
+                    .iter()
+                    .map(|arg| {
+                        let name = token_from_json(field(arg, "name", "Named argument")?)?;
+                        Ok((name, expr_from_json(field(arg, "value", "Named argument")?)?))
+                    })
+                    .collect::<Result<Vec<(Token, Expr)>, String>>()?,
+            },
+        },
+        "Grouping" => Expr::Grouping {
+            expression: expr("expression")?,
+        },
+        "Literal" => Expr::Literal {
+            value: match field(json, "value", kind)? {
+                value @ (Json::Array(_) | Json::Object(_)) => {
+                    return Err(format!("Literal value can't be {}", value.to_string()))
+                }
+                value => value.to_value(),
+            },
+        },
+        "Logical" => Expr::Logical {
+            left: expr("left")?,
+            operator: token("operator")?,
+            right: expr("right")?,
+        },
+        "Unary" => Expr::Unary {
+            operator: token("operator")?,
+            right: expr("right")?,
+        },
+        "Variable" => Expr::Variable {
+            name: token("name")?,
+        },
+        other => return Err(format!("Unknown expression kind '{}'", other)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const SOURCE: &str = "fun add(a: Number, b): Number {\n  return a + b;\n}\nvar f = fun (x, y = 1, ...r) { return -x; };\nvar i = 0;\nwhile (i < 2) {\n  if (i == 0 or false) print add(i, 1); else print f(x: i);\n  i = i + 1;\n}\nfor (c in \"ab\") print c;\n";
+
+    #[test]
+    fn round_trips_programs() {
+        let stmts = parse(SOURCE).unwrap();
+        let json = to_json(&stmts);
+        let loaded = from_json(&Json::parse(&json.to_string()).unwrap()).unwrap();
+
+        assert_eq!(to_json(&loaded), json);
+        let rendered = |stmts: &[Stmt]| stmts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(rendered(&loaded), rendered(&stmts));
+    }
+
+    #[test]
+    fn keeps_token_details() {
+        let json = to_json(&parse("print \"hi\";").unwrap());
+        let print = &json.get("statements").unwrap().as_array().unwrap()[0];
+
+        assert_eq!(
+            print.get("expression").unwrap().to_string(),
+            r#"{"kind":"Literal","value":"hi"}"#
+        );
+        assert_eq!(
+            print.get("keyword").unwrap().to_string(),
+            r#"{"type":"Print","lexeme":"print","literal":null,"line":1,"column":0}"#
+        );
+    }
+
+    #[test]
+    fn reports_malformed_nodes() {
+        let missing = Json::parse(r#"{"statements": [{"kind": "Print"}]}"#).unwrap();
+        assert_eq!(from_json(&missing).err().unwrap(), "Print node is missing 'keyword'");
+
+        let unknown = Json::parse(r#"{"statements": [{"kind": "Loop"}]}"#).unwrap();
+        assert_eq!(from_json(&unknown).err().unwrap(), "Unknown statement kind 'Loop'");
+    }
+}
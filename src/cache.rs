@@ -0,0 +1,664 @@
+use crate::error::Error;
+use crate::expr::{Expr, LiteralValue};
+use crate::scanner::{self, Token, TokenType};
+use crate::stmt::Stmt;
+use crate::typecheck::TypeChecker;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 5] = b"BOLTC";
+
+/// Version of the cache file layout. Bump it whenever `Stmt`, `Expr` or the
+/// encoding below changes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Where the cache for the script at `source` lives: next to it, with a
+/// `.boltc` extension.
+pub fn cache_path(source: &Path) -> PathBuf {
+    source.with_extension("boltc")
+}
+
+/// FNV-1a hash of a script's text. Unlike `DefaultHasher` it is stable
+/// across Rust releases, so cache files stay valid after a toolchain update.
+pub fn content_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Parses and type-checks `source`, reusing the cache next to `path` when it
+/// was written for the same text by this version of the interpreter. A fresh
+/// cache is written after a successful compile; failing to write it is not an
+/// error.
+pub fn load_or_compile(path: &Path, source: &str) -> Result<Vec<Stmt>, Error> {
+    let hash = content_hash(source);
+    let cache = cache_path(path);
+    if let Ok(stmts) = fs::read(&cache)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| decode(&bytes, hash))
+    {
+        return Ok(stmts);
+    }
+
+    let stmts = crate::parse(source)?;
+    TypeChecker::new()
+        .check_program(&stmts)
+        .map_err(|errors| Error::Type(errors.join("\n")))?;
+    if let Ok(bytes) = encode(&stmts, hash) {
+        let _ = fs::write(&cache, bytes);
+    }
+    Ok(stmts)
+}
+
+/// Serializes a parsed program for the script whose text hashes to `hash`.
+pub fn encode(stmts: &[Stmt], hash: u64) -> Result<Vec<u8>, String> {
+    let mut writer = Writer { bytes: vec![] };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u32(FORMAT_VERSION);
+    writer.str(env!("CARGO_PKG_VERSION"));
+    writer.u64(hash);
+    writer.u32(stmts.len() as u32);
+    for stmt in stmts {
+        writer.stmt(stmt)?;
+    }
+    Ok(writer.bytes)
+}
+
+/// Reads a program written by [`encode`]. Fails if the file is from another
+/// interpreter version or was written for different source text.
+pub fn decode(bytes: &[u8], hash: u64) -> Result<Vec<Stmt>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a Bolt cache file".to_string());
+    }
+    let format = reader.u32()?;
+    let version = reader.str()?;
+    if format != FORMAT_VERSION || version != env!("CARGO_PKG_VERSION") {
+        return Err(format!(
+            "Cache was written by bolt {} (format {})",
+            version, format
+        ));
+    }
+    if reader.u64()? != hash {
+        return Err("Cache is for a different version of the script".to_string());
+    }
+    let count = reader.u32()?;
+    let stmts = (0..count)
+        .map(|_| reader.stmt())
+        .collect::<Result<Vec<Stmt>, String>>()?;
+    if reader.position != bytes.len() {
+        return Err("Unexpected data after the program".to_string());
+    }
+    Ok(stmts)
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn token(&mut self, token: &Token) {
+        self.str(&token.token_type.to_string());
+        self.str(&token.lexeme);
+        match &token.literal {
+            None => self.u8(0),
+            Some(scanner::LiteralValue::FValue(x)) => {
+                self.u8(1);
+                self.f64(*x);
+            }
+            Some(scanner::LiteralValue::StringValue(s)) => {
+                self.u8(2);
+                self.str(s);
+            }
+        }
+        self.u32(token.lineNumber as u32);
+        self.u32(token.column as u32);
+    }
+
+    fn optional_token(&mut self, token: &Option<Token>) {
+        match token {
+            None => self.u8(0),
+            Some(token) => {
+                self.u8(1);
+                self.token(token);
+            }
+        }
+    }
+
+    fn tokens(&mut self, tokens: &[Token]) {
+        self.u32(tokens.len() as u32);
+        tokens.iter().for_each(|token| self.token(token));
+    }
+
+    fn optional_tokens(&mut self, tokens: &[Option<Token>]) {
+        self.u32(tokens.len() as u32);
+        tokens.iter().for_each(|token| self.optional_token(token));
+    }
+
+    fn defaults(&mut self, defaults: &[Option<Expr>]) -> Result<(), String> {
+        self.u32(defaults.len() as u32);
+        defaults.iter().try_for_each(|default| match default {
+            None => {
+                self.u8(0);
+                Ok(())
+            }
+            Some(default) => {
+                self.u8(1);
+                self.expr(default)
+            }
+        })
+    }
+
+    fn stmts(&mut self, stmts: &[Box<Stmt>]) -> Result<(), String> {
+        self.u32(stmts.len() as u32);
+        stmts.iter().try_for_each(|stmt| self.stmt(stmt))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Expression { expression } => {
+                self.u8(0);
+                self.expr(expression)
+            }
+            Stmt::Print {
+                keyword,
+                expression,
+            } => {
+                self.u8(1);
+                self.token(keyword);
+                self.expr(expression)
+            }
+            Stmt::Var {
+                name,
+                type_annotation,
+                initializer,
+            } => {
+                self.u8(2);
+                self.token(name);
+                self.optional_token(type_annotation);
+                self.expr(initializer)
+            }
+            Stmt::Block { statements } => {
+                self.u8(3);
+                self.stmts(statements)
+            }
+            Stmt::IfStmt {
+                keyword,
+                predicate,
+                then,
+                els,
+            } => {
+                self.u8(4);
+                self.token(keyword);
+                self.expr(predicate)?;
+                self.stmt(then)?;
+                match els {
+                    None => {
+                        self.u8(0);
+                        Ok(())
+                    }
+                    Some(els) => {
+                        self.u8(1);
+                        self.stmt(els)
+                    }
+                }
+            }
+            Stmt::WhileStmt {
+                keyword,
+                condition,
+                body,
+            } => {
+                self.u8(5);
+                self.token(keyword);
+                self.expr(condition)?;
+                self.stmt(body)
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                defaults,
+                rest,
+                return_type,
+                body,
+            } => {
+                self.u8(6);
+                self.token(name);
+                self.tokens(params);
+                self.optional_tokens(param_types);
+                self.defaults(defaults)?;
+                self.u8(*rest as u8);
+                self.optional_token(return_type);
+                self.stmts(body)
+            }
+            Stmt::ReturnStmt { keyword, value } => {
+                self.u8(7);
+                self.token(keyword);
+                match value {
+                    None => {
+                        self.u8(0);
+                        Ok(())
+                    }
+                    Some(value) => {
+                        self.u8(1);
+                        self.expr(value)
+                    }
+                }
+            }
+            Stmt::ForIn {
+                keyword,
+                variable,
+                iterable,
+                body,
+            } => {
+                self.u8(8);
+                self.token(keyword);
+                self.token(variable);
+                self.expr(iterable)?;
+                self.stmt(body)
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::AnonFunction {
+                paren,
+                arguments,
+                param_types,
+                defaults,
+                rest,
+                return_type,
+                body,
+            } => {
+                self.u8(0);
+                self.token(paren);
+                self.tokens(arguments);
+                self.optional_tokens(param_types);
+                self.defaults(defaults)?;
+                self.u8(*rest as u8);
+                self.optional_token(return_type);
+                self.stmts(body)
+            }
+            Expr::Assign { name, value } => {
+                self.u8(1);
+                self.token(name);
+                self.expr(value)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.u8(2);
+                self.expr(left)?;
+                self.token(operator);
+                self.expr(right)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                named,
+            } => {
+                self.u8(3);
+                self.expr(callee)?;
+                self.token(paren);
+                self.u32(arguments.len() as u32);
+                arguments
+                    .iter()
+                    .try_for_each(|argument| self.expr(argument))?;
+                self.u32(named.len() as u32);
+                named.iter().try_for_each(|(name, argument)| {
+                    self.token(name);
+                    self.expr(argument)
+                })
+            }
+            Expr::Grouping { expression } => {
+                self.u8(4);
+                self.expr(expression)
+            }
+            Expr::Literal { value } => {
+                self.u8(5);
+                self.literal(value)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.u8(6);
+                self.expr(left)?;
+                self.token(operator);
+                self.expr(right)
+            }
+            Expr::Unary { operator, right } => {
+                self.u8(7);
+                self.token(operator);
+                self.expr(right)
+            }
+            Expr::Variable { name } => {
+                self.u8(8);
+                self.token(name);
+                Ok(())
+            }
+        }
+    }
+
+    fn literal(&mut self, value: &LiteralValue) -> Result<(), String> {
+        match value {
+            LiteralValue::Nil => self.u8(0),
+            LiteralValue::True => self.u8(1),
+            LiteralValue::False => self.u8(2),
+            LiteralValue::Number(x) => {
+                self.u8(3);
+                self.f64(*x);
+            }
+            LiteralValue::StringValue(s) => {
+                self.u8(4);
+                self.str(s);
+            }
+            other => return Err(format!("Can't cache a {} literal", other.to_type())),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Cache file is truncated")?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn flag(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("Invalid flag {} in cache file", other)),
+        }
+    }
+
+    fn token(&mut self) -> Result<Token, String> {
+        let token_type: TokenType = self.str()?.parse()?;
+        let lexeme = self.str()?;
+        let literal = match self.u8()? {
+            0 => None,
+            1 => Some(scanner::LiteralValue::FValue(self.f64()?)),
+            2 => Some(scanner::LiteralValue::StringValue(self.str()?)),
+            other => return Err(format!("Invalid token literal tag {}", other)),
+        };
+        let line = self.u32()? as usize;
+        let column = self.u32()? as usize;
+        Ok(Token::new(token_type, lexeme, literal, line, column))
+    }
+
+    fn optional_token(&mut self) -> Result<Option<Token>, String> {
+        if self.flag()? {
+            self.token().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn tokens(&mut self) -> Result<Vec<Token>, String> {
+        (0..self.u32()?).map(|_| self.token()).collect()
+    }
+
+    fn optional_tokens(&mut self) -> Result<Vec<Option<Token>>, String> {
+        (0..self.u32()?).map(|_| self.optional_token()).collect()
+    }
+
+    fn defaults(&mut self) -> Result<Vec<Option<Expr>>, String> {
+        (0..self.u32()?)
+            .map(|_| match self.flag()? {
+                true => self.expr().map(Some),
+                false => Ok(None),
+            })
+            .collect()
+    }
+
+    fn stmts(&mut self) -> Result<Vec<Box<Stmt>>, String> {
+        (0..self.u32()?)
+            .map(|_| self.stmt().map(Box::new))
+            .collect()
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        Ok(match self.u8()? {
+            0 => Stmt::Expression {
+                expression: self.expr()?,
+            },
+            1 => Stmt::Print {
+                keyword: self.token()?,
+                expression: self.expr()?,
+            },
+            2 => Stmt::Var {
+                name: self.token()?,
+                type_annotation: self.optional_token()?,
+                initializer: self.expr()?,
+            },
+            3 => Stmt::Block {
+                statements: self.stmts()?,
+            },
+            4 => Stmt::IfStmt {
+                keyword: self.token()?,
+                predicate: self.expr()?,
+                then: Box::new(self.stmt()?),
+                els: match self.flag()? {
+                    true => Some(Box::new(self.stmt()?)),
+                    false => None,
+                },
+            },
+            5 => Stmt::WhileStmt {
+                keyword: self.token()?,
+                condition: self.expr()?,
+                body: Box::new(self.stmt()?),
+            },
+            6 => Stmt::Function {
+                name: self.token()?,
+                params: self.tokens()?,
+                param_types: self.optional_tokens()?,
+                defaults: self.defaults()?,
+                rest: self.flag()?,
+                return_type: self.optional_token()?,
+                body: self.stmts()?,
+            },
+            7 => Stmt::ReturnStmt {
+                keyword: self.token()?,
+                value: match self.flag()? {
+                    true => Some(self.expr()?),
+                    false => None,
+                },
+            },
+            8 => Stmt::ForIn {
+                keyword: self.token()?,
+                variable: self.token()?,
+                iterable: self.expr()?,
+                body: Box::new(self.stmt()?),
+            },
+            other => return Err(format!("Invalid statement tag {}", other)),
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        Ok(match self.u8()? {
+            0 => Expr::AnonFunction {
+                paren: self.token()?,
+                arguments: self.tokens()?,
+                param_types: self.optional_tokens()?,
+                defaults: self.defaults()?,
+                rest: self.flag()?,
+                return_type: self.optional_token()?,
+                body: self.stmts()?,
+            },
+            1 => Expr::Assign {
+                name: self.token()?,
+                value: Box::new(self.expr()?),
+            },
+            2 => Expr::Binary {
+                left: Box::new(self.expr()?),
+                operator: self.token()?,
+                right: Box::new(self.expr()?),
+            },
+            3 => Expr::Call {
+                callee: Box::new(self.expr()?),
+                paren: self.token()?,
+                arguments: (0..self.u32()?)
+                    .map(|_| self.expr())
+                    .collect::<Result<Vec<Expr>, String>>()?,
+                named: (0..self.u32()?)
+                    .map(|_| Ok((self.token()?, self.expr()?)))
+                    .collect::<Result<Vec<(Token, Expr)>, String>>()?,
+            },
+            4 => Expr::Grouping {
+                expression: Box::new(self.expr()?),
+            },
+            5 => Expr::Literal {
+                value: self.literal()?,
+            },
+            6 => Expr::Logical {
+                left: Box::new(self.expr()?),
+                operator: self.token()?,
+                right: Box::new(self.expr()?),
+            },
+            7 => Expr::Unary {
+                operator: self.token()?,
+                right: Box::new(self.expr()?),
+            },
+            8 => Expr::Variable {
+                name: self.token()?,
+            },
+            other => return Err(format!("Invalid expression tag {}", other)),
+        })
+    }
+
+    fn literal(&mut self) -> Result<LiteralValue, String> {
+        Ok(match self.u8()? {
+            0 => LiteralValue::Nil,
+            1 => LiteralValue::True,
+            2 => LiteralValue::False,
+            3 => LiteralValue::Number(self.f64()?),
+            4 => LiteralValue::StringValue(self.str()?),
+            other => return Err(format!("Invalid literal tag {}", other)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, parse};
+
+    const SOURCE: &str = "fun add(a: Number, b): Number {\n  return a + b;\n}\nvar f = fun (x, y = x, ...r) { return -x; };\nfor (var i = 0; i < 2; i = i + 1) {\n  if (i == 0 or nil) print add(i, 1.5); else print f(\"s\", y: 2);\n}\nfor (n in 0..=i) print n;\n";
+
+    #[test]
+    fn round_trips_programs() {
+        let stmts = parse(SOURCE).unwrap();
+        let hash = content_hash(SOURCE);
+        let decoded = decode(&encode(&stmts, hash).unwrap(), hash).unwrap();
+
+        assert_eq!(ast::to_json(&decoded), ast::to_json(&stmts));
+    }
+
+    #[test]
+    fn rejects_stale_and_foreign_files() {
+        let stmts = parse(SOURCE).unwrap();
+        let bytes = encode(&stmts, content_hash(SOURCE)).unwrap();
+
+        assert_eq!(
+            decode(&bytes, content_hash("print 1;")).err(),
+            Some("Cache is for a different version of the script".to_string())
+        );
+        assert_eq!(
+            decode(b"{}", 0).err(),
+            Some("Cache file is truncated".to_string())
+        );
+
+        let mut old = bytes.clone();
+        old[MAGIC.len()] = 0;
+        assert!(decode(&old, content_hash(SOURCE))
+            .err()
+            .unwrap()
+            .starts_with("Cache was written by bolt"));
+
+        let truncated = &bytes[..bytes.len() - 3];
+        assert!(decode(truncated, content_hash(SOURCE)).is_err());
+    }
+
+    #[test]
+    fn writes_the_cache_next_to_the_script() {
+        let dir = std::env::temp_dir().join("bolt-cache-test");
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script.bolt");
+        let cache = cache_path(&script);
+        let _ = fs::remove_file(&cache);
+
+        let stmts = load_or_compile(&script, SOURCE).unwrap();
+        let bytes = fs::read(&cache).unwrap();
+        assert_eq!(
+            decode(&bytes, content_hash(SOURCE)).unwrap().len(),
+            stmts.len()
+        );
+
+        // A script that no longer type-checks isn't served from the old cache.
+        let broken = "var x: Number = \"a\";";
+        assert!(matches!(
+            load_or_compile(&script, broken),
+            Err(Error::Type(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
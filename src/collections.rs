@@ -0,0 +1,239 @@
+//! Natives that work on arrays and take Bolt functions as callbacks, such as
+//! `map(items, f)` and `reduce(items, f, initial)`. An error raised by a
+//! callback stops the native and is passed on unchanged.
+
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::sandbox::{Budget, CancellationToken, VALUE_COST};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// Registers every collection native in `interpreter`.
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_fn("map", map);
+    interpreter.register_fn("filter", filter);
+    interpreter.register_fn("reduce", reduce);
+    interpreter.register_fn("each", each);
+    interpreter.register_fn("sort_by", sort_by);
+    interpreter.register_fn("any", any);
+    interpreter.register_fn("all", all);
+    interpreter.register_fn("zip", zip);
+    let budget = interpreter.budget.clone();
+    let cancellation = interpreter.cancellation.clone();
+    interpreter.register_fn("range", move |start: f64, end: f64| {
+        range(&budget, &cancellation, start, end)
+    });
+    interpreter.register_fn("enumerate", enumerate);
+}
+
+/// Checks that argument `index` (1-based) of native `name` can be called.
+fn callback(name: &str, index: usize, f: &LiteralValue) -> Result<(), String> {
+    match f {
+        LiteralValue::Callable { .. } => Ok(()),
+        other => Err(format!(
+            "{} expected argument {} to be Callable but got {}",
+            name,
+            index,
+            other.to_type()
+        )),
+    }
+}
+
+fn is_truthy(value: &LiteralValue) -> bool {
+    value.is_truthy() == LiteralValue::True
+}
+
+fn map(items: Vec<LiteralValue>, f: LiteralValue) -> Result<Vec<LiteralValue>, String> {
+    callback("map", 2, &f)?;
+    items.into_iter().map(|item| f.call(&vec![item])).collect()
+}
+
+fn filter(items: Vec<LiteralValue>, f: LiteralValue) -> Result<Vec<LiteralValue>, String> {
+    callback("filter", 2, &f)?;
+    let mut kept = vec![];
+    for item in items {
+        if is_truthy(&f.call(&vec![item.clone()])?) {
+            kept.push(item);
+        }
+    }
+    Ok(kept)
+}
+
+fn reduce(
+    items: Vec<LiteralValue>,
+    f: LiteralValue,
+    initial: LiteralValue,
+) -> Result<LiteralValue, String> {
+    callback("reduce", 2, &f)?;
+    items
+        .into_iter()
+        .try_fold(initial, |acc, item| f.call(&vec![acc, item]))
+}
+
+fn each(items: Vec<LiteralValue>, f: LiteralValue) -> Result<(), String> {
+    callback("each", 2, &f)?;
+    for item in items {
+        f.call(&vec![item])?;
+    }
+    Ok(())
+}
+
+/// Sorts a copy of `items` by the key `f` returns for each of them. Keys must
+/// be all Numbers or all Strings; items with equal keys keep their order.
+fn sort_by(items: Vec<LiteralValue>, f: LiteralValue) -> Result<Vec<LiteralValue>, String> {
+    callback("sort_by", 2, &f)?;
+    let mut keyed = vec![];
+    for item in items {
+        let key = f.call(&vec![item.clone()])?;
+        match (&key, keyed.first().map(|(first, _)| first)) {
+            (LiteralValue::Number(_), None | Some(LiteralValue::Number(_)))
+            | (LiteralValue::StringValue(_), None | Some(LiteralValue::StringValue(_))) => {}
+            (key, Some(first)) => {
+                return Err(format!(
+                    "sort_by can't compare a {} key with a {} key",
+                    first.to_type(),
+                    key.to_type()
+                ))
+            }
+            (key, None) => {
+                return Err(format!(
+                    "sort_by keys must be Numbers or Strings but got {}",
+                    key.to_type()
+                ))
+            }
+        }
+        keyed.push((key, item));
+    }
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (LiteralValue::Number(a), LiteralValue::Number(b)) => {
+            a.partial_cmp(b).unwrap_or(Ordering::Equal)
+        }
+        (LiteralValue::StringValue(a), LiteralValue::StringValue(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    });
+    Ok(keyed.into_iter().map(|(_, item)| item).collect())
+}
+
+fn any(items: Vec<LiteralValue>, f: LiteralValue) -> Result<bool, String> {
+    callback("any", 2, &f)?;
+    for item in items {
+        if is_truthy(&f.call(&vec![item])?) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn all(items: Vec<LiteralValue>, f: LiteralValue) -> Result<bool, String> {
+    callback("all", 2, &f)?;
+    for item in items {
+        if !is_truthy(&f.call(&vec![item])?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Pairs up the elements of two arrays, stopping at the shorter one.
+fn zip(left: Vec<LiteralValue>, right: Vec<LiteralValue>) -> Result<Vec<LiteralValue>, String> {
+    Ok(left
+        .into_iter()
+        .zip(right)
+        .map(|(a, b)| LiteralValue::Array(Rc::new(vec![a, b])))
+        .collect())
+}
+
+/// The whole numbers from `start` up to, but not including, `end`. Fails
+/// before allocating if the array wouldn't fit in the memory budget, and
+/// checks the time limit and cancellation while it fills the array.
+fn range(
+    budget: &Budget,
+    cancellation: &CancellationToken,
+    start: f64,
+    end: f64,
+) -> Result<Vec<LiteralValue>, String> {
+    if start.fract() != 0.0 || end.fract() != 0.0 {
+        return Err(format!(
+            "range expected whole numbers but got {} and {}",
+            start, end
+        ));
+    }
+    let count = (end - start).max(0.0) as usize;
+    budget.ensure_available(count.saturating_mul(VALUE_COST))?;
+    let mut numbers = vec![];
+    for i in 0..count {
+        if i % 1024 == 0 {
+            budget.check_time()?;
+            if cancellation.is_cancelled() {
+                return Err("range was cancelled".to_string());
+            }
+        }
+        numbers.push(LiteralValue::Number(start + i as f64));
+    }
+    Ok(numbers)
+}
+
+fn enumerate(items: Vec<LiteralValue>) -> Result<Vec<LiteralValue>, String> {
+    Ok(items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| LiteralValue::Array(Rc::new(vec![LiteralValue::Number(i as f64), item])))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{eval, Config, Error, Interpreter, Limit, Value};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn calls_bolt_callbacks() {
+        let source = "var xs = range(1, 6);
+var evens = filter(map(xs, x => x * 2), x => x > 4);
+reduce(evens, (acc, x) => acc + x, 0);";
+        assert_eq!(eval(source).unwrap(), Value::Number(24.0));
+
+        let source = "fun word(i) { if (i == 0) return \"pear\"; if (i == 1) return \"fig\"; return \"apple\"; }
+sort_by(map(range(0, 3), word), w => len(w));";
+        assert_eq!(
+            eval(source).unwrap().to_string(),
+            "[\"fig\", \"pear\", \"apple\"]"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_callback_error() {
+        let source = "var seen = 0;
+fun check(x) { seen = seen + 1; assert(x < 2, \"too big\"); return x; }
+each(range(0, 5), check);";
+        let mut interpreter = Interpreter::new();
+        let err = crate::run(&mut interpreter, source).unwrap_err();
+        assert!(
+            matches!(err, Error::Runtime(ref msg) if msg.contains("Assertion failed: too big"))
+        );
+        assert_eq!(interpreter.get_global("seen"), Some(Value::Number(3.0)));
+
+        let err = eval("map(range(0, 2), 5);").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("map expected argument 2 to be Callable but got Number"));
+        let err = eval("sort_by(range(0, 2), x => { if (x == 0) return 1; \"a\"; });").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("sort_by can't compare a Number key with a String key"));
+    }
+
+    #[test]
+    fn range_respects_the_memory_limit() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let config = Config {
+            max_memory: Some(1_000_000),
+            ..Config::default()
+        };
+        let mut interpreter = Interpreter::with_config(output.clone(), output, config);
+        let err = crate::run(&mut interpreter, "var xs = range(0, 100000000);").unwrap_err();
+        assert!(matches!(err, Error::Limit(Limit::Memory, _)));
+        assert!(crate::run(&mut interpreter, "var xs = range(0, 1000);").is_ok());
+    }
+}
@@ -0,0 +1,349 @@
+use crate::debugger::DebugHook;
+use crate::expr::LiteralValue;
+use crate::expr::Expr;
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use std::collections::{BTreeMap, HashMap};
+
+/// Line, function and branch coverage of one program, collected by
+/// installing it as the interpreter's debug hook.
+pub struct Coverage {
+    /// Hit count of every line that holds a statement.
+    lines: BTreeMap<usize, u64>,
+    /// Every function in the program with the line it is declared on.
+    /// Anonymous functions are named like the interpreter names them.
+    functions: Vec<(String, usize)>,
+    /// Call counts by function name and declaration line, so functions that
+    /// share a name are counted apart.
+    calls: HashMap<(String, usize), u64>,
+    /// How often each `if` took its `then` and its `else` branch, by the
+    /// line of the `if` keyword.
+    branches: BTreeMap<usize, [u64; 2]>,
+}
+
+fn percent(hit: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        hit as f64 * 100.0 / total as f64
+    }
+}
+
+/// Formats sorted line numbers as ranges, like `3, 7-9`.
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Coverage {
+    /// Finds everything in `program` that can be covered. Install the result
+    /// on an interpreter that runs the same, unoptimized program.
+    pub fn new(program: &[Stmt]) -> Self {
+        let mut coverage = Self {
+            lines: BTreeMap::new(),
+            functions: vec![],
+            calls: HashMap::new(),
+            branches: BTreeMap::new(),
+        };
+        for stmt in program {
+            coverage.find_stmt(stmt);
+        }
+        coverage
+    }
+
+    fn find_stmt(&mut self, stmt: &Stmt) {
+        if let Some(line) = stmt.line().filter(|_| !matches!(stmt, Stmt::Block { .. })) {
+            self.lines.insert(line, 0);
+        }
+        match stmt {
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+                self.find_expr(expression)
+            }
+            Stmt::Var { initializer, .. } => self.find_expr(initializer),
+            Stmt::Block { statements } => statements.iter().for_each(|s| self.find_stmt(s)),
+            Stmt::IfStmt {
+                keyword,
+                predicate,
+                then,
+                els,
+            } => {
+                self.branches.insert(keyword.lineNumber, [0, 0]);
+                self.find_expr(predicate);
+                self.find_stmt(then);
+                if let Some(els) = els {
+                    self.find_stmt(els);
+                }
+            }
+            Stmt::WhileStmt {
+                condition, body, ..
+            } => {
+                self.find_expr(condition);
+                self.find_stmt(body);
+            }
+            Stmt::ForIn { iterable, body, .. } => {
+                self.find_expr(iterable);
+                self.find_stmt(body);
+            }
+            Stmt::Function {
+                name,
+                defaults,
+                body,
+                ..
+            } => {
+                self.functions.push((name.lexeme.clone(), name.lineNumber));
+                defaults.iter().flatten().for_each(|d| self.find_expr(d));
+                body.iter().for_each(|s| self.find_stmt(s));
+            }
+            Stmt::ReturnStmt { value, .. } => {
+                if let Some(value) = value {
+                    self.find_expr(value);
+                }
+            }
+        }
+    }
+
+    fn find_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::AnonFunction {
+                paren,
+                defaults,
+                body,
+                ..
+            } => {
+                let name = format!("<anon line {}>", paren.lineNumber);
+                self.functions.push((name, paren.lineNumber));
+                defaults.iter().flatten().for_each(|d| self.find_expr(d));
+                body.iter().for_each(|s| self.find_stmt(s));
+            }
+            Expr::Assign { value, .. } => self.find_expr(value),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.find_expr(left);
+                self.find_expr(right);
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                named,
+                ..
+            } => {
+                self.find_expr(callee);
+                arguments.iter().for_each(|a| self.find_expr(a));
+                named.iter().for_each(|(_, a)| self.find_expr(a));
+            }
+            Expr::Grouping { expression } => self.find_expr(expression),
+            Expr::Unary { right, .. } => self.find_expr(right),
+            Expr::Literal { .. } | Expr::Variable { .. } => (),
+        }
+    }
+
+    pub fn unexecuted_lines(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, hits)| **hits == 0)
+            .map(|(line, _)| *line)
+            .collect()
+    }
+
+    pub fn unexecuted_functions(&self) -> Vec<(String, usize)> {
+        self.functions
+            .iter()
+            .filter(|function| !self.calls.contains_key(function))
+            .cloned()
+            .collect()
+    }
+
+    /// Branches that never ran, as the line of their `if` and `"then"` or
+    /// `"else"`. An `if` without an `else` still has an else branch: the
+    /// condition being false.
+    pub fn untaken_branches(&self) -> Vec<(usize, &'static str)> {
+        let mut untaken = vec![];
+        for (line, taken) in &self.branches {
+            for (count, branch) in taken.iter().zip(["then", "else"]) {
+                if *count == 0 {
+                    untaken.push((*line, branch));
+                }
+            }
+        }
+        untaken
+    }
+
+    /// The totals, followed by everything that never ran.
+    pub fn summary(&self) -> String {
+        let lines_hit = self.lines.len() - self.unexecuted_lines().len();
+        let functions_hit = self.functions.len() - self.unexecuted_functions().len();
+        let branches_hit = self.branches.len() * 2 - self.untaken_branches().len();
+
+        let mut summary = format!(
+            "Lines:     {}/{} ({:.1}%)\nFunctions: {}/{} ({:.1}%)\nBranches:  {}/{} ({:.1}%)\n",
+            lines_hit,
+            self.lines.len(),
+            percent(lines_hit, self.lines.len()),
+            functions_hit,
+            self.functions.len(),
+            percent(functions_hit, self.functions.len()),
+            branches_hit,
+            self.branches.len() * 2,
+            percent(branches_hit, self.branches.len() * 2),
+        );
+        let lines = self.unexecuted_lines();
+        if !lines.is_empty() {
+            summary += &format!("Unexecuted lines: {}\n", line_ranges(&lines));
+        }
+        for (name, line) in self.unexecuted_functions() {
+            summary += &format!("Unexecuted function: {} (line {})\n", name, line);
+        }
+        for (line, branch) in self.untaken_branches() {
+            summary += &format!("Untaken branch: {} of the if on line {}\n", branch, line);
+        }
+        summary
+    }
+
+    /// The coverage in LCOV's tracefile format, for `source` (the path the
+    /// program was read from).
+    pub fn lcov(&self, source: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", source);
+
+        for (name, line) in &self.functions {
+            lcov += &format!("FN:{},{}\n", line, name);
+        }
+        for function in &self.functions {
+            let calls = self.calls.get(function).unwrap_or(&0);
+            lcov += &format!("FNDA:{},{}\n", calls, function.0);
+        }
+        lcov += &format!(
+            "FNF:{}\nFNH:{}\n",
+            self.functions.len(),
+            self.functions.len() - self.unexecuted_functions().len()
+        );
+
+        for (line, taken) in &self.branches {
+            let reached = taken[0] + taken[1] > 0;
+            for (branch, count) in taken.iter().enumerate() {
+                let count = if reached { count.to_string() } else { "-".to_string() };
+                lcov += &format!("BRDA:{},0,{},{}\n", line, branch, count);
+            }
+        }
+        lcov += &format!(
+            "BRF:{}\nBRH:{}\n",
+            self.branches.len() * 2,
+            self.branches.len() * 2 - self.untaken_branches().len()
+        );
+
+        for (line, hits) in &self.lines {
+            lcov += &format!("DA:{},{}\n", line, hits);
+        }
+        lcov += &format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            self.lines.len(),
+            self.lines.len() - self.unexecuted_lines().len()
+        );
+        lcov
+    }
+}
+
+impl DebugHook for Coverage {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        if let Stmt::Block { .. } = stmt {
+            return Ok(());
+        }
+        if let Some(line) = stmt.line() {
+            *self.lines.entry(line).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        *self.calls.entry((name.to_string(), line)).or_insert(0) += 1;
+        Ok(())
+    }
+
+    fn branch(&mut self, line: usize, taken: bool) {
+        let branches = self.branches.entry(line).or_insert([0, 0]);
+        branches[if taken { 0 } else { 1 }] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, run_unoptimized};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const SOURCE: &str = "fun used(n) {\n  if (n > 0) {\n    return n;\n  } else {\n    return -n;\n  }\n}\nfun unused() {\n  print 1;\n}\nvar twice = fun (x) { return x * 2; };\nprint used(3);\n";
+
+    fn cover(source: &str) -> Rc<RefCell<Coverage>> {
+        let coverage = Rc::new(RefCell::new(Coverage::new(&parse(source).unwrap())));
+        let mut interpreter = Interpreter::with_output(
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(Vec::new())),
+        );
+        interpreter.set_debug_hook(coverage.clone());
+        run_unoptimized(&mut interpreter, source).unwrap();
+        coverage
+    }
+
+    #[test]
+    fn finds_unexecuted_lines_functions_and_branches() {
+        let coverage = cover(SOURCE);
+        let coverage = coverage.borrow();
+
+        assert_eq!(coverage.unexecuted_lines(), vec![5, 9]);
+        assert_eq!(
+            coverage.unexecuted_functions(),
+            vec![("unused".to_string(), 8), ("<anon line 11>".to_string(), 11)]
+        );
+        assert_eq!(coverage.untaken_branches(), vec![(2, "else")]);
+        assert!(coverage.summary().starts_with("Lines:     6/8 (75.0%)\nFunctions: 1/3 (33.3%)\nBranches:  1/2 (50.0%)\n"));
+    }
+
+    #[test]
+    fn counts_functions_with_the_same_name_apart() {
+        let source = "fun outer() {\n  fun helper() { return 1; }\n  return helper();\n}\nfun other() {\n  fun helper() { return 2; }\n  return 0;\n}\nprint outer() + other();\n";
+        let coverage = cover(source);
+
+        assert_eq!(
+            coverage.borrow().unexecuted_functions(),
+            vec![("helper".to_string(), 6)]
+        );
+    }
+
+    #[test]
+    fn writes_lcov() {
+        let coverage = cover(SOURCE);
+        let lcov = coverage.borrow().lcov("script.bolt");
+
+        assert!(lcov.starts_with("TN:\nSF:script.bolt\nFN:1,used\n"));
+        assert!(lcov.contains("FNDA:1,used\nFNDA:0,unused\n"));
+        assert!(lcov.contains("BRDA:2,0,0,1\nBRDA:2,0,1,0\nBRF:2\nBRH:1\n"));
+        assert!(lcov.contains("DA:5,0\n"));
+        assert!(lcov.ends_with("LF:8\nLH:6\nend_of_record\n"));
+    }
+
+    #[test]
+    fn formats_line_ranges() {
+        assert_eq!(line_ranges(&[1, 3, 4, 5, 9]), "1, 3-5, 9");
+    }
+}
@@ -0,0 +1,540 @@
+use crate::debugger::{environment_chain, scope_name, DebugHook, Stepper};
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::json::Json;
+use crate::lsp::{read_message, write_message};
+use crate::run_unoptimized;
+use crate::sandbox::{Capabilities, Config};
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Bolt scripts run on a single thread, which is the only one reported.
+const THREAD_ID: usize = 1;
+
+/// What the adapter should do after handling a request.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Wait,
+    /// `configurationDone`: start running the program.
+    Start,
+    /// A `continue` or step request: let the paused script go on.
+    Resume,
+    Disconnect,
+}
+
+/// A Debug Adapter Protocol server for one debugging session. Requests are
+/// read from `reader` before the program starts, whenever it is paused and
+/// after it has finished. Script output is forwarded as `output` events.
+pub struct Adapter {
+    reader: Box<dyn BufRead>,
+    writer: Rc<RefCell<dyn Write>>,
+    seq: usize,
+    stepper: Stepper,
+    program: Option<String>,
+    /// Environment of each frame, outermost first, parallel to the
+    /// stepper's call stack.
+    environments: Vec<Option<Rc<RefCell<Environment>>>>,
+    /// Variables of the scopes handed out since the last pause. A scope's
+    /// `variablesReference` is its index here plus one.
+    variables: Vec<Vec<(String, LiteralValue)>>,
+    output: Rc<RefCell<Vec<u8>>>,
+    stop_reason: &'static str,
+    disconnected: bool,
+}
+
+/// Serves one debugging session over `reader`/`writer`, running the program
+/// named by the client's `launch` request.
+pub fn run(reader: Box<dyn BufRead>, writer: Rc<RefCell<dyn Write>>) -> Result<(), String> {
+    let adapter = Rc::new(RefCell::new(Adapter::new(reader, writer)));
+
+    loop {
+        match adapter.borrow_mut().next_action(None)? {
+            Action::Start => break,
+            Action::Disconnect => return Ok(()),
+            Action::Wait | Action::Resume => (),
+        }
+    }
+
+    let program = adapter.borrow().program.clone();
+    let result = match program.map(|path| fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))) {
+        Some(Ok(source)) => {
+            let output = adapter.borrow().output.clone();
+            let config = Config {
+                capabilities: Capabilities::all(),
+                ..Config::default()
+            };
+            let mut interpreter = Interpreter::with_config(output.clone(), output, config);
+            interpreter.set_debug_hook(adapter.clone());
+            run_unoptimized(&mut interpreter, &source).map_err(|e| e.to_string())
+        }
+        Some(Err(msg)) => Err(msg),
+        None => Err("No program was launched".to_string()),
+    };
+
+    let mut adapter = adapter.borrow_mut();
+    if adapter.disconnected {
+        return Ok(());
+    }
+    adapter.finish(result)?;
+    while adapter.next_action(None)? != Action::Disconnect {}
+    Ok(())
+}
+
+impl Adapter {
+    pub fn new(reader: Box<dyn BufRead>, writer: Rc<RefCell<dyn Write>>) -> Self {
+        Self {
+            reader,
+            writer,
+            seq: 0,
+            stepper: Stepper::new(),
+            program: None,
+            environments: vec![None],
+            variables: vec![],
+            output: Rc::new(RefCell::new(Vec::new())),
+            stop_reason: "entry",
+            disconnected: false,
+        }
+    }
+
+    fn send(&mut self, mut members: Vec<(&str, Json)>) -> Result<(), String> {
+        self.seq += 1;
+        members.insert(0, ("seq", self.seq.into()));
+        let message = Json::object(members);
+        let mut writer = self.writer.borrow_mut();
+        write_message(&mut &mut *writer, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> Result<(), String> {
+        self.send(vec![("type", "event".into()), ("event", event.into()), ("body", body)])
+    }
+
+    /// Sends whatever the script printed since the last call.
+    fn flush_output(&mut self) -> Result<(), String> {
+        let printed = String::from_utf8_lossy(&self.output.borrow()).to_string();
+        self.output.borrow_mut().clear();
+        if printed.is_empty() {
+            return Ok(());
+        }
+        self.event(
+            "output",
+            Json::object(vec![("category", "stdout".into()), ("output", printed.into())]),
+        )
+    }
+
+    fn finish(&mut self, result: Result<(), String>) -> Result<(), String> {
+        self.flush_output()?;
+        let exit_code: usize = match result {
+            Ok(_) => 0,
+            Err(msg) => {
+                let body = Json::object(vec![
+                    ("category", "stderr".into()),
+                    ("output", format!("{}\n", msg).into()),
+                ]);
+                self.event("output", body)?;
+                1
+            }
+        };
+        self.event("exited", Json::object(vec![("exitCode", exit_code.into())]))?;
+        self.event("terminated", Json::object(vec![]))
+    }
+
+    /// Reads the next request and answers it. A closed stream counts as a
+    /// disconnect.
+    fn next_action(&mut self, interpreter: Option<&Interpreter>) -> Result<Action, String> {
+        let Some(request) = read_message(&mut self.reader)? else {
+            self.disconnected = true;
+            return Ok(Action::Disconnect);
+        };
+        let command = request.get("command").and_then(|c| c.as_str()).unwrap_or("").to_string();
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        let request_seq = request.get("seq").cloned().unwrap_or(Json::Null);
+
+        let (action, body) = match self.handle(&command, &arguments, interpreter) {
+            Ok(handled) => handled,
+            Err(msg) => {
+                return self
+                    .send(vec![
+                        ("type", "response".into()),
+                        ("request_seq", request_seq),
+                        ("success", false.into()),
+                        ("command", command.as_str().into()),
+                        ("message", msg.into()),
+                    ])
+                    .map(|_| Action::Wait);
+            }
+        };
+        self.send(vec![
+            ("type", "response".into()),
+            ("request_seq", request_seq),
+            ("success", true.into()),
+            ("command", command.as_str().into()),
+            ("body", body),
+        ])?;
+        if command == "initialize" {
+            self.event("initialized", Json::object(vec![]))?;
+        }
+        if action == Action::Disconnect {
+            self.disconnected = true;
+        }
+        Ok(action)
+    }
+
+    fn handle(
+        &mut self,
+        command: &str,
+        arguments: &Json,
+        interpreter: Option<&Interpreter>,
+    ) -> Result<(Action, Json), String> {
+        let empty = Json::object(vec![]);
+        match command {
+            "initialize" => Ok((
+                Action::Wait,
+                Json::object(vec![("supportsConfigurationDoneRequest", true.into())]),
+            )),
+            "launch" => {
+                let program = arguments.get("program").and_then(|p| p.as_str());
+                self.program = Some(program.ok_or("launch needs a 'program'")?.to_string());
+                if !arguments.get("stopOnEntry").and_then(|s| s.as_bool()).unwrap_or(false) {
+                    self.stepper.resume();
+                }
+                Ok((Action::Wait, empty))
+            }
+            "setBreakpoints" => {
+                let lines: Vec<usize> = arguments
+                    .get("breakpoints")
+                    .and_then(|b| b.as_array())
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .filter_map(|b| b.get("line").and_then(|l| l.as_usize()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                self.stepper.breakpoints = lines.iter().copied().collect();
+                let verified = lines
+                    .iter()
+                    .map(|line| Json::object(vec![("verified", true.into()), ("line", (*line).into())]))
+                    .collect::<Vec<Json>>();
+                Ok((Action::Wait, Json::object(vec![("breakpoints", verified.into())])))
+            }
+            "configurationDone" => Ok((Action::Start, empty)),
+            "threads" => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                Ok((Action::Wait, Json::object(vec![("threads", vec![thread].into())])))
+            }
+            "stackTrace" => {
+                let path = self.program.clone().unwrap_or_default();
+                let frames: Vec<Json> = self
+                    .stepper
+                    .frames()
+                    .iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        Json::object(vec![
+                            ("id", id.into()),
+                            ("name", frame.name.as_str().into()),
+                            ("line", frame.line.into()),
+                            ("column", 1usize.into()),
+                            ("source", Json::object(vec![("path", path.as_str().into())])),
+                        ])
+                    })
+                    .collect();
+                let total = frames.len();
+                Ok((
+                    Action::Wait,
+                    Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())]),
+                ))
+            }
+            "scopes" => {
+                let frame = arguments.get("frameId").and_then(|f| f.as_usize()).unwrap_or(0);
+                let index = self
+                    .environments
+                    .len()
+                    .checked_sub(frame + 1)
+                    .ok_or_else(|| format!("Unknown frame {}", frame))?;
+                let environment = self.environments[index]
+                    .clone()
+                    .ok_or("The program is not running")?;
+
+                let levels = environment_chain(&environment);
+                let count = levels.len();
+                let mut scopes = vec![];
+                for (i, level) in levels.into_iter().enumerate() {
+                    self.variables.push(level);
+                    scopes.push(Json::object(vec![
+                        ("name", scope_name(i, count).into()),
+                        ("variablesReference", self.variables.len().into()),
+                        ("expensive", (i + 1 == count).into()),
+                    ]));
+                }
+                Ok((Action::Wait, Json::object(vec![("scopes", scopes.into())])))
+            }
+            "variables" => {
+                let reference = arguments
+                    .get("variablesReference")
+                    .and_then(|r| r.as_usize())
+                    .unwrap_or(0);
+                let level = reference
+                    .checked_sub(1)
+                    .and_then(|i| self.variables.get(i))
+                    .ok_or_else(|| format!("Unknown variables reference {}", reference))?;
+                let variables: Vec<Json> = level
+                    .iter()
+                    .map(|(name, value)| {
+                        Json::object(vec![
+                            ("name", name.as_str().into()),
+                            ("value", value.to_string().into()),
+                            ("type", value.to_type().into()),
+                            ("variablesReference", 0usize.into()),
+                        ])
+                    })
+                    .collect();
+                Ok((Action::Wait, Json::object(vec![("variables", variables.into())])))
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                if interpreter.is_none() {
+                    return Err("The program is not paused".to_string());
+                }
+                match command {
+                    "continue" => self.stepper.resume(),
+                    "next" => self.stepper.step_over(),
+                    "stepIn" => self.stepper.step_into(),
+                    _ => self.stepper.step_out(),
+                }
+                self.stop_reason = if command == "continue" { "breakpoint" } else { "step" };
+                Ok((Action::Resume, empty))
+            }
+            "disconnect" => Ok((Action::Disconnect, empty)),
+            other => Err(format!("Unsupported request '{}'", other)),
+        }
+    }
+}
+
+impl DebugHook for Adapter {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        if let Some(environment) = self.environments.last_mut() {
+            *environment = Some(interpreter.environment.clone());
+        }
+        if !self.stepper.should_pause(stmt) {
+            return Ok(());
+        }
+
+        self.flush_output()?;
+        let reason = if self.stepper.breakpoints.contains(&self.stepper.line()) {
+            "breakpoint"
+        } else {
+            self.stop_reason
+        };
+        let body = Json::object(vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+        ]);
+        self.event("stopped", body)?;
+
+        self.variables.clear();
+        loop {
+            match self.next_action(Some(interpreter))? {
+                Action::Resume => return Ok(()),
+                Action::Disconnect => return Err("Debugger disconnected".to_string()),
+                Action::Wait | Action::Start => (),
+            }
+        }
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        self.stepper.enter(name);
+        self.environments.push(Some(interpreter.environment.clone()));
+        Ok(())
+    }
+
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {
+        self.stepper.exit();
+        if self.environments.len() > 1 {
+            self.environments.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Frames `requests` the way a DAP client would and runs a session over
+    /// them, returning every message the adapter sent.
+    fn session(source: &str, requests: Vec<(&str, Json)>) -> Vec<Json> {
+        let path = std::env::temp_dir().join(format!("bolt-dap-{:?}.bolt", std::thread::current().id()));
+        fs::write(&path, source).unwrap();
+
+        let mut input = Vec::new();
+        let mut all = vec![
+            ("initialize", Json::object(vec![])),
+            (
+                "launch",
+                Json::object(vec![("program", path.display().to_string().into())]),
+            ),
+        ];
+        all.extend(requests);
+        for (seq, (command, arguments)) in all.into_iter().enumerate() {
+            let request = Json::object(vec![
+                ("seq", (seq + 1).into()),
+                ("type", "request".into()),
+                ("command", command.into()),
+                ("arguments", arguments),
+            ]);
+            write_message(&mut input, &request).unwrap();
+        }
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        run(Box::new(Cursor::new(input)), output.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let bytes = output.borrow().clone();
+        let mut reader = Cursor::new(bytes);
+        let mut messages = vec![];
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+        messages
+            .iter()
+            .filter(|m| m.get("event").and_then(|e| e.as_str()) == Some(event))
+            .collect()
+    }
+
+    fn response<'a>(messages: &'a [Json], command: &str) -> Vec<&'a Json> {
+        messages
+            .iter()
+            .filter(|m| m.get("type").and_then(|t| t.as_str()) == Some("response"))
+            .filter(|m| m.get("command").and_then(|c| c.as_str()) == Some(command))
+            .collect()
+    }
+
+    const SOURCE: &str = "var total = 0;\nfun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\ntotal = add(1, 2);\nprint total;\n";
+
+    fn breakpoint_at(line: usize) -> Json {
+        Json::object(vec![(
+            "breakpoints",
+            vec![Json::object(vec![("line", line.into())])].into(),
+        )])
+    }
+
+    #[test]
+    fn stops_at_breakpoint_and_exposes_scopes() {
+        let messages = session(
+            SOURCE,
+            vec![
+                ("setBreakpoints", breakpoint_at(4)),
+                ("configurationDone", Json::object(vec![])),
+                ("threads", Json::object(vec![])),
+                ("stackTrace", Json::object(vec![("threadId", 1usize.into())])),
+                ("scopes", Json::object(vec![("frameId", 0usize.into())])),
+                ("variables", Json::object(vec![("variablesReference", 1usize.into())])),
+                ("continue", Json::object(vec![])),
+                ("disconnect", Json::object(vec![])),
+            ],
+        );
+
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(stopped[0].get("body").unwrap().get("reason").unwrap().as_str(), Some("breakpoint"));
+
+        let frames = response(&messages, "stackTrace")[0].get("body").unwrap().get("stackFrames").unwrap();
+        let frames = frames.as_array().unwrap();
+        assert_eq!(frames[0].get("name").unwrap().as_str(), Some("add"));
+        assert_eq!(frames[0].get("line").unwrap().as_usize(), Some(4));
+        assert_eq!(frames[1].get("name").unwrap().as_str(), Some("<script>"));
+        assert_eq!(frames[1].get("line").unwrap().as_usize(), Some(6));
+
+        let scopes = response(&messages, "scopes")[0].get("body").unwrap().get("scopes").unwrap();
+        let names: Vec<&str> = scopes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s.get("name").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Locals", "Globals"]);
+
+        let variables = response(&messages, "variables")[0].get("body").unwrap().get("variables").unwrap();
+        let variables: Vec<(String, String)> = variables
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| {
+                let field = |key| v.get(key).unwrap().as_str().unwrap().to_string();
+                (field("name"), field("value"))
+            })
+            .collect();
+        assert_eq!(
+            variables,
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+                ("sum".to_string(), "3".to_string()),
+            ]
+        );
+
+        let output = events(&messages, "output");
+        assert_eq!(output[0].get("body").unwrap().get("output").unwrap().as_str(), Some("3\n"));
+        let exited = events(&messages, "exited");
+        assert_eq!(exited[0].get("body").unwrap().get("exitCode").unwrap().as_usize(), Some(0));
+        assert_eq!(events(&messages, "terminated").len(), 1);
+    }
+
+    #[test]
+    fn steps_through_the_program() {
+        let source = "var total = 0;\nfun add(a, b) {\n  return a + b;\n}\ntotal = add(1, 2);\nprint total;\n";
+        let trace = || ("stackTrace", Json::object(vec![]));
+        let messages = session(
+            source,
+            vec![
+                ("setBreakpoints", breakpoint_at(1)),
+                ("configurationDone", Json::object(vec![])),
+                trace(),
+                ("next", Json::object(vec![])),
+                trace(),
+                ("next", Json::object(vec![])),
+                trace(),
+                ("stepIn", Json::object(vec![])),
+                trace(),
+                ("stepOut", Json::object(vec![])),
+                trace(),
+                ("disconnect", Json::object(vec![])),
+            ],
+        );
+        let lines: Vec<(String, usize)> = response(&messages, "stackTrace")
+            .iter()
+            .map(|r| {
+                let top = &r.get("body").unwrap().get("stackFrames").unwrap().as_array().unwrap()[0];
+                (
+                    top.get("name").unwrap().as_str().unwrap().to_string(),
+                    top.get("line").unwrap().as_usize().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("<script>".to_string(), 1),
+                ("<script>".to_string(), 2),
+                ("<script>".to_string(), 5),
+                ("add".to_string(), 3),
+                ("<script>".to_string(), 6),
+            ]
+        );
+        assert_eq!(events(&messages, "terminated").len(), 0, "disconnect stops the script");
+    }
+}
@@ -0,0 +1,431 @@
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Callbacks the interpreter makes while it runs, installed with
+/// `Interpreter::set_debug_hook`. Returning an error from a callback stops
+/// the script with that error.
+pub trait DebugHook {
+    /// Called before each statement runs.
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String>;
+
+    /// Called when a function starts running, before its first statement,
+    /// with the line it is declared on and the arguments it was called with.
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called when a function returns or fails, with its result.
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {}
+
+    /// Called when the `if` on `line` has evaluated its condition, with
+    /// whether the `then` branch is taken.
+    fn branch(&mut self, line: usize, taken: bool) {}
+}
+
+/// Several hooks installed at once. Each callback goes to every hook in
+/// order, stopping at the first error.
+pub struct Hooks(pub Vec<Rc<RefCell<dyn DebugHook>>>);
+
+impl DebugHook for Hooks {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        for hook in &self.0 {
+            hook.borrow_mut().before_stmt(stmt, interpreter)?;
+        }
+        Ok(())
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        for hook in &self.0 {
+            hook.borrow_mut().enter_function(name, line, args, interpreter)?;
+        }
+        Ok(())
+    }
+
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {
+        for hook in &self.0 {
+            hook.borrow_mut().exit_function(result, interpreter);
+        }
+    }
+
+    fn branch(&mut self, line: usize, taken: bool) {
+        for hook in &self.0 {
+            hook.borrow_mut().branch(line, taken);
+        }
+    }
+}
+
+/// A function that is currently running and the line it has reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepMode {
+    Continue,
+    Into,
+    /// Pause at the next statement whose call depth is at most this.
+    Over(usize),
+    /// Pause at the next statement whose call depth is below this.
+    Out(usize),
+}
+
+/// Breakpoints, stepping and the call stack, shared by the terminal debugger
+/// and the DAP server. Starts out paused before the first statement.
+pub struct Stepper {
+    pub breakpoints: BTreeSet<usize>,
+    frames: Vec<Frame>,
+    mode: StepMode,
+}
+
+impl Default for Stepper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stepper {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            frames: vec![Frame {
+                name: "<script>".to_string(),
+                line: 0,
+            }],
+            mode: StepMode::Into,
+        }
+    }
+
+    /// The call stack, innermost frame first.
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.iter().rev().cloned().collect()
+    }
+
+    pub fn line(&self) -> usize {
+        self.frames.last().map(|frame| frame.line).unwrap_or(0)
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = StepMode::Continue;
+    }
+
+    pub fn step_into(&mut self) {
+        self.mode = StepMode::Into;
+    }
+
+    pub fn step_over(&mut self) {
+        self.mode = StepMode::Over(self.frames.len());
+    }
+
+    pub fn step_out(&mut self) {
+        self.mode = StepMode::Out(self.frames.len());
+    }
+
+    pub fn enter(&mut self, name: &str) {
+        self.frames.push(Frame {
+            name: name.to_string(),
+            line: 0,
+        });
+    }
+
+    pub fn exit(&mut self) {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+    }
+
+    /// Records that `stmt` is about to run and says whether to pause before
+    /// it. Blocks are skipped, since their first statement is reported on
+    /// its own.
+    pub fn should_pause(&mut self, stmt: &Stmt) -> bool {
+        if let Stmt::Block { .. } = stmt {
+            return false;
+        }
+        let Some(line) = stmt.line() else {
+            return false;
+        };
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+
+        let depth = self.frames.len();
+        let stepped = match self.mode {
+            StepMode::Continue => false,
+            StepMode::Into => true,
+            StepMode::Over(max) => depth <= max,
+            StepMode::Out(below) => depth < below,
+        };
+        stepped || self.breakpoints.contains(&line)
+    }
+}
+
+/// The variables visible from `environment`, one list per level of the
+/// chain, innermost first. The last level holds the globals.
+pub fn environment_chain(environment: &Rc<RefCell<Environment>>) -> Vec<Vec<(String, LiteralValue)>> {
+    let mut levels = vec![];
+    let mut current = Some(environment.clone());
+    while let Some(env) = current {
+        levels.push(env.borrow().values());
+        current = env.borrow().enclosing.clone();
+    }
+    levels
+}
+
+/// Name of level `index` of an environment chain with `count` levels.
+pub fn scope_name(index: usize, count: usize) -> String {
+    if index + 1 == count {
+        "Globals".to_string()
+    } else if index == 0 {
+        "Locals".to_string()
+    } else {
+        format!("Closure {}", index)
+    }
+}
+
+const HELP: &str = "\
+Commands:
+  c, continue      run until the next breakpoint
+  s, step          step into the next statement
+  n, next          step over function calls
+  o, out           run until the current function returns
+  b, break LINE    set a breakpoint
+  d, delete LINE   remove a breakpoint
+  bt, stack        show the call stack
+  v, vars          show the variables of every scope
+  p, print NAME    show a variable
+  l, list          show the source around the current line
+  q, quit          stop the script";
+
+/// Interactive terminal debugger for `bolt debug`. Reads commands from
+/// `input` whenever the script pauses.
+pub struct Debugger {
+    stepper: Stepper,
+    source: Vec<String>,
+    input: Box<dyn BufRead>,
+    output: Rc<RefCell<dyn Write>>,
+}
+
+impl Debugger {
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Rc<RefCell<dyn Write>>) -> Self {
+        Self {
+            stepper: Stepper::new(),
+            source: source.lines().map(|line| line.to_string()).collect(),
+            input,
+            output,
+        }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source
+            .get(line.wrapping_sub(1))
+            .map(|text| text.trim())
+            .unwrap_or("")
+    }
+
+    fn say(&self, text: &str) -> Result<(), String> {
+        writeln!(self.output.borrow_mut(), "{}", text).map_err(|e| e.to_string())
+    }
+
+    /// Reads and runs commands until one of them resumes the script.
+    fn pause(&mut self, interpreter: &Interpreter) -> Result<(), String> {
+        let line = self.stepper.line();
+        self.say(&format!("Paused at line {}: {}", line, self.source_line(line)))?;
+
+        loop {
+            write!(self.output.borrow_mut(), "(bolt-debug) ").map_err(|e| e.to_string())?;
+            self.output.borrow_mut().flush().map_err(|e| e.to_string())?;
+
+            let mut command = String::new();
+            let read = self.input.read_line(&mut command).map_err(|e| e.to_string())?;
+            if read == 0 {
+                // Nobody is left to type commands: run the rest of the script.
+                self.stepper.breakpoints.clear();
+                self.stepper.resume();
+                return Ok(());
+            }
+
+            let mut words = command.split_whitespace();
+            let name = words.next().unwrap_or("");
+            let argument = words.next();
+            match name {
+                "c" | "continue" => {
+                    self.stepper.resume();
+                    return Ok(());
+                }
+                "s" | "step" => {
+                    self.stepper.step_into();
+                    return Ok(());
+                }
+                "n" | "next" => {
+                    self.stepper.step_over();
+                    return Ok(());
+                }
+                "o" | "out" => {
+                    self.stepper.step_out();
+                    return Ok(());
+                }
+                "b" | "break" | "d" | "delete" => {
+                    match argument.and_then(|arg| arg.parse::<usize>().ok()) {
+                        Some(line) if name.starts_with('b') => {
+                            self.stepper.breakpoints.insert(line);
+                            self.say(&format!("Breakpoint at line {}", line))?;
+                        }
+                        Some(line) => {
+                            self.stepper.breakpoints.remove(&line);
+                            self.say(&format!("Removed breakpoint at line {}", line))?;
+                        }
+                        None => self.say(&format!("Usage: {} LINE", name))?,
+                    }
+                }
+                "bt" | "stack" => {
+                    for (i, frame) in self.stepper.frames().iter().enumerate() {
+                        self.say(&format!("#{} {} at line {}", i, frame.name, frame.line))?;
+                    }
+                }
+                "v" | "vars" => {
+                    let levels = environment_chain(&interpreter.environment);
+                    for (i, level) in levels.iter().enumerate() {
+                        self.say(&format!("{}:", scope_name(i, levels.len())))?;
+                        for (name, value) in level {
+                            self.say(&format!("  {} = {}", name, value.to_string()))?;
+                        }
+                    }
+                }
+                "p" | "print" => match argument {
+                    Some(variable) => match interpreter.environment.borrow().get(variable) {
+                        Some(value) => self.say(&format!("{} = {}", variable, value.to_string()))?,
+                        None => self.say(&format!("'{}' is not defined", variable))?,
+                    },
+                    None => self.say("Usage: print NAME")?,
+                },
+                "l" | "list" => {
+                    let first = line.saturating_sub(3).max(1);
+                    for number in first..=(line + 3).min(self.source.len()) {
+                        let marker = if number == line { "->" } else { "  " };
+                        self.say(&format!("{} {:>4} {}", marker, number, self.source[number - 1]))?;
+                    }
+                }
+                "q" | "quit" => return Err("Debugger quit".to_string()),
+                "h" | "help" => self.say(HELP)?,
+                "" => (),
+                other => self.say(&format!(
+                    "Unknown command '{}'. Type 'help' for a list of commands.",
+                    other
+                ))?,
+            }
+        }
+    }
+}
+
+impl DebugHook for Debugger {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        if self.stepper.should_pause(stmt) {
+            self.pause(interpreter)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        self.stepper.enter(name);
+        Ok(())
+    }
+
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {
+        self.stepper.exit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_unoptimized;
+    use std::io::Cursor;
+
+    const SCRIPT: &str = "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\nvar x = 1;\nvar y = add(x, 2);\nprint y;\n";
+
+    /// Runs `SCRIPT` under the debugger with the given commands and returns
+    /// the debugger transcript and the script's own output.
+    fn debug(commands: &str) -> (String, String) {
+        let transcript = Rc::new(RefCell::new(Vec::new()));
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let input = Box::new(Cursor::new(commands.to_string()));
+        let debugger = Debugger::new(SCRIPT, input, transcript.clone());
+
+        let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+        interpreter.set_debug_hook(Rc::new(RefCell::new(debugger)));
+        run_unoptimized(&mut interpreter, SCRIPT).unwrap();
+
+        let transcript = String::from_utf8(transcript.borrow().clone()).unwrap();
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        (transcript, output)
+    }
+
+    fn paused_lines(transcript: &str) -> Vec<usize> {
+        transcript
+            .lines()
+            .filter_map(|line| line.split("Paused at line ").nth(1))
+            .filter_map(|rest| rest.split(':').next()?.parse().ok())
+            .collect()
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_shows_variables() {
+        let (transcript, output) = debug("b 3\nc\nbt\nv\np x\nc\n");
+
+        assert_eq!(paused_lines(&transcript), vec![1, 3]);
+        assert!(transcript.contains("#0 add at line 3\n#1 <script> at line 6\n"));
+        assert!(transcript.contains("Locals:\n  a = 1\n  b = 2\n  sum = 3\nGlobals:\n"));
+        assert!(transcript.contains("x = 1\n"));
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn steps_into_over_and_out() {
+        let (into, _) = debug("s\ns\ns\ns\nc\n");
+        assert_eq!(paused_lines(&into), vec![1, 5, 6, 2, 3]);
+
+        let (over, _) = debug("s\ns\nn\nc\n");
+        assert_eq!(paused_lines(&over), vec![1, 5, 6, 7]);
+
+        let (out, _) = debug("b 2\nc\no\nc\n");
+        assert_eq!(paused_lines(&out), vec![1, 2, 7]);
+    }
+
+    #[test]
+    fn quit_stops_the_script() {
+        let transcript = Rc::new(RefCell::new(Vec::new()));
+        let input = Box::new(Cursor::new("q\n".to_string()));
+        let debugger = Debugger::new(SCRIPT, input, transcript);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debug_hook(Rc::new(RefCell::new(debugger)));
+        let result = run_unoptimized(&mut interpreter, SCRIPT);
+        assert_eq!(result.unwrap_err().message(), "Debugger quit");
+    }
+}
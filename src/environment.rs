@@ -8,6 +8,12 @@ pub struct Environment {
     pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         Self {
@@ -28,6 +34,17 @@ impl Environment {
         }
     }
 
+    /// The variables defined at this level only, sorted by name.
+    pub fn values(&self) -> Vec<(String, LiteralValue)> {
+        let mut values: Vec<(String, LiteralValue)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        values
+    }
+
     pub fn get(&self, name: &str) -> Option<LiteralValue> {
         let value = self.values.get(name);
 
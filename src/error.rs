@@ -0,0 +1,38 @@
+use crate::sandbox::Limit;
+use std::fmt;
+
+/// Errors returned by the public entry points, tagged with the stage of the
+/// pipeline that produced them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Scan(String),
+    Parse(String),
+    /// Mismatches found by the static type checker, one per line.
+    Type(String),
+    Runtime(String),
+    /// The script ran past one of the limits of its sandbox `Config`.
+    Limit(Limit, String),
+    /// The script was stopped through its interpreter's cancellation token.
+    Interrupted(String),
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Scan(msg) => msg,
+            Error::Parse(msg) => msg,
+            Error::Type(msg) => msg,
+            Error::Runtime(msg) => msg,
+            Error::Limit(_, msg) => msg,
+            Error::Interrupted(msg) => msg,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for Error {}
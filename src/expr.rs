@@ -9,8 +9,9 @@ use std::{
 use crate::{
     environment::{self, Environment},
     interpreter::Interpreter,
+    sandbox::ENVIRONMENT_COST,
     scanner::{self, Token, TokenType},
-    stmt::Stmt,
+    stmt::{self, Stmt},
 };
 
 #[derive(Clone)]
@@ -22,9 +23,78 @@ pub enum LiteralValue {
     Nil,
     Callable {
         name: String,
-        arity: usize,
-        fun: Rc<dyn Fn(&Vec<LiteralValue>) -> LiteralValue>,
+        arity: Arity,
+        fun: CallableFn,
+        /// Set for functions declared in a script; natives have none.
+        source: Option<Rc<FunctionSource>>,
     },
+    /// An immutable list, as produced by `json_parse`.
+    Array(Rc<Vec<LiteralValue>>),
+    /// An immutable map that keeps its keys in insertion order.
+    Object(Rc<Vec<(String, LiteralValue)>>),
+    /// The numbers from `start` to `end` one apart, as made by `start..end`
+    /// or `start..=end`.
+    Range {
+        start: f64,
+        end: f64,
+        inclusive: bool,
+    },
+}
+
+/// A function's implementation, called with the positional arguments and
+/// the `name: value` ones.
+pub type CallableFn =
+    Rc<dyn Fn(&Vec<LiteralValue>, &[(String, LiteralValue)]) -> Result<LiteralValue, String>>;
+
+/// How many arguments a function accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` if a rest parameter takes any number of extra arguments.
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exact(count: usize) -> Self {
+        Self {
+            min: count,
+            max: Some(count),
+        }
+    }
+
+    /// The arity of a parameter list. Parameters with a default may be left
+    /// out, and a trailing rest parameter lifts the upper bound.
+    pub fn of(defaults: &[Option<Expr>], rest: bool) -> Self {
+        let fixed = defaults.len() - rest as usize;
+        Self {
+            min: defaults[..fixed].iter().filter(|d| d.is_none()).count(),
+            max: (!rest).then_some(fixed),
+        }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+
+    /// `2` for an exact arity, `1..=3` for a range and `1..` when there is
+    /// no upper bound.
+    pub fn to_string(&self) -> String {
+        match self.max {
+            Some(max) if max == self.min => max.to_string(),
+            Some(max) => format!("{}..={}", self.min, max),
+            None => format!("{}..", self.min),
+        }
+    }
+}
+
+/// What a script function was built from, so that it can be saved in a
+/// snapshot and rebuilt later.
+pub struct FunctionSource {
+    /// The `fun` statement, or for anonymous functions an expression
+    /// statement holding the `fun` expression.
+    pub declaration: Stmt,
+    /// The environment the function closes over.
+    pub closure: Rc<RefCell<Environment>>,
 }
 
 impl std::fmt::Debug for LiteralValue {
@@ -42,17 +112,33 @@ impl PartialEq for LiteralValue {
                     name,
                     arity,
                     fun: _,
+                    ..
                 },
                 LiteralValue::Callable {
                     name: name2,
                     arity: arity2,
                     fun: _,
+                    ..
                 },
             ) => name == name2 && arity == arity2,
             (LiteralValue::StringValue(x), LiteralValue::StringValue(y)) => x == y,
             (LiteralValue::True, LiteralValue::True) => true,
             (LiteralValue::False, LiteralValue::False) => true,
             (LiteralValue::Nil, LiteralValue::Nil) => true,
+            (LiteralValue::Array(x), LiteralValue::Array(y)) => x == y,
+            (LiteralValue::Object(x), LiteralValue::Object(y)) => x == y,
+            (
+                LiteralValue::Range {
+                    start,
+                    end,
+                    inclusive,
+                },
+                LiteralValue::Range {
+                    start: start2,
+                    end: end2,
+                    inclusive: inclusive2,
+                },
+            ) => start == start2 && end == end2 && inclusive == inclusive2,
             _ => false,
         }
     }
@@ -60,7 +146,7 @@ impl PartialEq for LiteralValue {
 
 fn unwrap_as_f64(literal: Option<scanner::LiteralValue>) -> f64 {
     match literal {
-        Some(scanner::LiteralValue::FValue(x)) => x as f64,
+        Some(scanner::LiteralValue::FValue(x)) => x,
         _ => panic!("Could not unwrap as f32"),
     }
 }
@@ -84,7 +170,29 @@ impl LiteralValue {
                 name,
                 arity,
                 fun: _,
-            } => format!("{name}|{arity}"),
+                ..
+            } => format!("{name}|{}", arity.to_string()),
+            LiteralValue::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            LiteralValue::Object(members) => format!(
+                "{{{}}}",
+                members
+                    .iter()
+                    .map(|(key, value)| format!("\"{}\": {}", key, value.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            LiteralValue::Range {
+                start,
+                end,
+                inclusive,
+            } => format!("{}..{}{}", start, if *inclusive { "=" } else { "" }, end),
         }
     }
 
@@ -99,7 +207,11 @@ impl LiteralValue {
                 name: _,
                 arity: _,
                 fun,
+                ..
             } => "Callable",
+            LiteralValue::Array(_) => "Array",
+            LiteralValue::Object(_) => "Object",
+            LiteralValue::Range { .. } => "Range",
         }
     }
 
@@ -115,6 +227,73 @@ impl LiteralValue {
         }
     }
 
+    /// Calls this value with `args`, checking that it is callable and that
+    /// the number of arguments matches its arity.
+    pub fn call(&self, args: &Vec<LiteralValue>) -> Result<LiteralValue, String> {
+        self.call_with(args, &[])
+    }
+
+    /// Like [`LiteralValue::call`], but also passes `name: value` arguments.
+    /// Only functions declared in a script accept those.
+    pub fn call_with(
+        &self,
+        args: &Vec<LiteralValue>,
+        named: &[(String, LiteralValue)],
+    ) -> Result<LiteralValue, String> {
+        match self {
+            LiteralValue::Callable {
+                name,
+                arity,
+                fun,
+                source,
+            } => {
+                if source.is_none() && !named.is_empty() {
+                    return Err(format!("Callable {} doesn't take named arguments", name));
+                }
+                let count = args.len() + named.len();
+                if !arity.accepts(count) {
+                    return Err(format!(
+                        "Callable {} expected {} arguments but got {}",
+                        name,
+                        arity.to_string(),
+                        count
+                    ));
+                }
+                fun(args, named)
+            }
+            other => Err(format!("{} is not callable", other.to_type())),
+        }
+    }
+
+    /// How many numbers a range goes through, or 0 for other values.
+    pub fn range_len(&self) -> u64 {
+        match self {
+            LiteralValue::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let span = end - start;
+                let count = if *inclusive {
+                    span.floor() + 1.0
+                } else {
+                    span.ceil()
+                };
+                count.max(0.0) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    /// The numbers a range goes through, or nothing for other values.
+    pub fn range_numbers(&self) -> impl DoubleEndedIterator<Item = f64> {
+        let start = match self {
+            LiteralValue::Range { start, .. } => *start,
+            _ => 0.0,
+        };
+        (0..self.range_len()).map(move |i| start + i as f64)
+    }
+
     pub fn from_bool(b: bool) -> Self {
         if b {
             LiteralValue::True
@@ -133,7 +312,7 @@ impl LiteralValue {
                 }
             }
             Self::StringValue(s) => {
-                if s.len() == 0 {
+                if s.is_empty() {
                     Self::True
                 } else {
                     Self::False
@@ -142,11 +321,10 @@ impl LiteralValue {
             Self::True => Self::False,
             Self::False => Self::True,
             Self::Nil => Self::True,
-            Self::Callable {
-                name: _,
-                arity: _,
-                fun,
-            } => panic!("Can not use callable as a falsy value"),
+            Self::Callable { .. } => Self::False,
+            Self::Array(items) => Self::from_bool(items.is_empty()),
+            Self::Object(members) => Self::from_bool(members.is_empty()),
+            Self::Range { .. } => Self::from_bool(self.range_numbers().next().is_none()),
         }
     }
 
@@ -160,7 +338,7 @@ impl LiteralValue {
                 }
             }
             Self::StringValue(s) => {
-                if s.len() == 0 {
+                if s.is_empty() {
                     Self::False
                 } else {
                     Self::True
@@ -169,11 +347,10 @@ impl LiteralValue {
             Self::True => Self::True,
             Self::False => Self::False,
             Self::Nil => Self::False,
-            Self::Callable {
-                name: _,
-                arity: _,
-                fun,
-            } => panic!("Can not use callable as a truthy"),
+            Self::Callable { .. } => Self::True,
+            Self::Array(items) => Self::from_bool(!items.is_empty()),
+            Self::Object(members) => Self::from_bool(!members.is_empty()),
+            Self::Range { .. } => Self::from_bool(self.range_numbers().next().is_some()),
         }
     }
 }
@@ -183,6 +360,12 @@ pub enum Expr {
     AnonFunction {
         paren: Token,
         arguments: Vec<Token>,
+        param_types: Vec<Option<Token>>,
+        /// Default value of each parameter, parallel to `arguments`.
+        defaults: Vec<Option<Expr>>,
+        /// Whether the last parameter is `...rest`.
+        rest: bool,
+        return_type: Option<Token>,
         body: Vec<Box<Stmt>>,
     },
     Assign {
@@ -198,6 +381,8 @@ pub enum Expr {
         callee: Box<Expr>,
         paren: Token,
         arguments: Vec<Expr>,
+        /// `name: value` arguments, which follow the positional ones.
+        named: Vec<(Token, Expr)>,
     },
     Grouping {
         expression: Box<Expr>,
@@ -226,15 +411,35 @@ impl std::fmt::Debug for Expr {
 }
 
 impl Expr {
+    /// Source line of the expression, taken from its leftmost token. Bare
+    /// literals carry no token and have no line.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::AnonFunction { paren, .. } => Some(paren.lineNumber),
+            Expr::Assign { name, .. } | Expr::Variable { name } => Some(name.lineNumber),
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => {
+                left.line().or(Some(operator.lineNumber))
+            }
+            Expr::Call { callee, paren, .. } => callee.line().or(Some(paren.lineNumber)),
+            Expr::Grouping { expression } => expression.line(),
+            Expr::Literal { .. } => None,
+            Expr::Unary { operator, .. } => Some(operator.lineNumber),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn to_string(&self) -> String {
         match self {
             Expr::AnonFunction {
-                paren: _,
                 arguments,
-                body: _,
-            } => format!("anon{}", arguments.len()),
-            Expr::Assign { name, value } => format!("{name:?} = {}", value.to_string()),
+                defaults,
+                rest,
+                ..
+            } => format!(
+                "(fun ({}))",
+                stmt::params_to_string(arguments, defaults, *rest)
+            ),
+            Expr::Assign { name, value } => format!("(= {} {})", name.lexeme, value.to_string()),
             Expr::Binary {
                 left,
                 operator,
@@ -247,11 +452,24 @@ impl Expr {
             ),
             Expr::Call {
                 callee,
-                paren,
                 arguments,
-            } => format!("({} {:?})", (*callee).to_string(), arguments),
+                named,
+                ..
+            } => format!(
+                "(call {})",
+                std::iter::once(callee.as_ref())
+                    .chain(arguments)
+                    .map(|expr| expr.to_string())
+                    .chain(
+                        named
+                            .iter()
+                            .map(|(name, expr)| format!("{}: {}", name.lexeme, expr.to_string()))
+                    )
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             Expr::Grouping { expression } => format!("(group {})", (*expression).to_string()),
-            Expr::Literal { value } => format!("{}", value.to_string()),
+            Expr::Literal { value } => value.to_string(),
             Expr::Unary { operator, right } => {
                 let operator_str = &operator.lexeme.clone();
                 let right_str = (*right).to_string();
@@ -264,60 +482,62 @@ impl Expr {
                 right,
             } => format!(
                 "({} {} {})",
-                operator.to_string(),
+                operator.lexeme,
                 left.to_string(),
                 right.to_string()
             ),
         }
     }
 
-    pub fn evaluate(&self, environment: Rc<RefCell<Environment>>) -> Result<LiteralValue, String> {
-        let env = environment;
+    pub fn evaluate(&self, interpreter: &Interpreter) -> Result<LiteralValue, String> {
+        let env = interpreter.environment.clone();
         match self {
             Expr::AnonFunction {
                 paren,
                 arguments,
+                defaults,
+                rest,
                 body,
+                ..
             } => {
-                let arity = arguments.clone();
-                let env = env.clone();
+                let host = interpreter.clone();
                 let arguments: Vec<Token> = arguments.iter().map(|t| (*t).clone()).collect();
+                let defaults = defaults.clone();
+                let rest = *rest;
                 let body: Vec<Box<Stmt>> = body.iter().map(|b| (*b).clone()).collect();
                 let paren = paren.clone();
-                let len = arguments.len().clone();
-                
-                let fun_impl = move |args: &Vec<LiteralValue>| {
-                    let mut anon_int = Interpreter::for_anon(env.clone());
-                    for (i, arg) in args.iter().enumerate() {
-                        anon_int
-                            .environment
-                            .borrow_mut()
-                            .define(arguments[i].lexeme.clone(), (*arg).clone());
-                    }
-
-                    for i in 0..(body.len()) {
-                        anon_int.interpret(vec![&body[i]]).expect(&format!(
-                            "Evaluating failed inside anon function at line {}",
-                            paren.lineNumber
-                        ));
+                let arity = Arity::of(&defaults, rest);
 
-                        if let Some(value) = anon_int.specials.borrow_mut().get("return") {
-                            return value;
-                        }
-                    }
-
-                    LiteralValue::Nil
+                let fun_impl = move |args: &Vec<LiteralValue>, named: &[(String, LiteralValue)]| {
+                    host.budget.allocate(ENVIRONMENT_COST)?;
+                    let mut anon_int = Interpreter::for_anon(&host);
+                    let name = format!("<anon line {}>", paren.lineNumber);
+                    let in_anon =
+                        |msg| format!("{} (in anon function at line {})", msg, paren.lineNumber);
+                    let args = anon_int
+                        .bind_arguments(&name, &arguments, &defaults, rest, args, named)
+                        .map_err(in_anon)?;
+                    anon_int
+                        .run_function(&name, paren.lineNumber, &args, &body)
+                        .map_err(in_anon)
                 };
 
                 // let anon_env = Interpreter::for_anon(environment.clone());
                 Ok(LiteralValue::Callable {
                     name: "anon_funtion".to_string(),
-                    arity: len,
+                    arity,
                     fun: Rc::new(fun_impl),
+                    source: Some(Rc::new(FunctionSource {
+                        declaration: Stmt::Expression {
+                            expression: self.clone(),
+                        },
+                        closure: env,
+                    })),
                 })
             }
             Expr::Assign { name, value } => {
-                let new_value = (*value).evaluate(env.clone())?;
+                let new_value = (*value).evaluate(interpreter)?;
+                interpreter.budget.check_value(&new_value)?;
                 let assign_success = env.borrow_mut().assign(&name.lexeme, new_value.clone());
                 if assign_success {
                     Ok(new_value)
@@ -337,29 +557,36 @@ impl Expr {
             },
             Expr::Call {
                 callee,
-                paren: _,
+                paren,
                 arguments,
+                named,
             } => {
-                let callable = (*callee).evaluate(env.clone())?;
-                match callable {
-                    LiteralValue::Callable { name, arity, fun } => {
-                        if arguments.len() != arity {
-                            return Err(format!(
-                                "Callable {} expected {} arguments but got {}",
-                                name,
-                                arity,
-                                arguments.len()
-                            ));
-                        }
-                        let mut arg_vals = vec![];
-                        for arg in arguments {
-                            let val = arg.evaluate(env.clone()).unwrap();
-                            arg_vals.push(val);
-                        }
-
-                        Ok(fun(&arg_vals))
+                interpreter.cancellation.check(paren.lineNumber)?;
+                let callable = (*callee).evaluate(interpreter)?;
+                if let LiteralValue::Callable { .. } = callable {
+                    let mut arg_vals = vec![];
+                    for arg in arguments {
+                        let val = arg.evaluate(interpreter)?;
+                        arg_vals.push(val);
+                    }
+                    let mut named_vals = vec![];
+                    for (name, arg) in named {
+                        named_vals.push((name.lexeme.clone(), arg.evaluate(interpreter)?));
                     }
-                    other => Err(format!("{} is not callable", other.to_type())),
+
+                    let value = match callable.call_with(&arg_vals, &named_vals) {
+                        Ok(value) => value,
+                        Err(msg) => {
+                            // A native stopped by cancellation reports it like
+                            // the interpreter does, with the line of the call.
+                            interpreter.cancellation.check(paren.lineNumber)?;
+                            return Err(msg);
+                        }
+                    };
+                    interpreter.budget.check_value(&value)?;
+                    Ok(value)
+                } else {
+                    Err(format!("{} is not callable", callable.to_type()))
                 }
             }
             Expr::Literal { value } => Ok((*value).clone()),
@@ -369,109 +596,42 @@ impl Expr {
                 right,
             } => match operator.token_type {
                 TokenType::Or => {
-                    let lhs_value = left.evaluate(env.clone())?;
+                    let lhs_value = left.evaluate(interpreter)?;
                     let lhs_true = lhs_value.is_truthy();
                     if lhs_true == LiteralValue::True {
                         Ok(lhs_value)
                     } else {
-                        right.evaluate(env.clone())
+                        right.evaluate(interpreter)
                     }
                 }
                 TokenType::And => {
-                    let lhs_value = left.evaluate(env.clone())?;
+                    let lhs_value = left.evaluate(interpreter)?;
                     let lhs_true = lhs_value.is_truthy();
                     if lhs_true == LiteralValue::False {
                         Ok(lhs_true)
                     } else {
-                        right.evaluate(env)
+                        right.evaluate(interpreter)
                     }
                 }
                 ttype => Err(format!("Invalid token in logical expression: {}", ttype)),
             },
-            Expr::Grouping { expression } => expression.evaluate(env),
+            Expr::Grouping { expression } => expression.evaluate(interpreter),
             Expr::Unary { operator, right } => {
-                let right = right.evaluate(env)?;
-
-                match (&right, operator.token_type) {
-                    (LiteralValue::Number(x), TokenType::Minus) => Ok(LiteralValue::Number(-x)),
-                    (_, TokenType::Minus) => {
-                        Err(format!("Minus not implemented for {}", right.to_type()))
-                    }
-                    (any, TokenType::Bang) => Ok(any.is_falsy()),
-                    (_, ttype) => Err(format!("{} is not a valid unary operator", ttype)),
-                }
+                let right = right.evaluate(interpreter)?;
+                apply_unary(operator.token_type, &right)
             }
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let left = left.evaluate(env.clone())?;
-                let right = right.evaluate(env.clone())?;
-
-                match (&left, operator.token_type, &right) {
-                    (LiteralValue::Number(x), TokenType::Plus, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::Number(x + y))
-                    }
-                    (LiteralValue::Number(x), TokenType::Minus, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::Number(x - y))
-                    }
-                    (LiteralValue::Number(x), TokenType::Star, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::Number(x * y))
-                    }
-                    (LiteralValue::Number(x), TokenType::Slash, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::Number(x / y))
-                    }
-                    (LiteralValue::Number(x), TokenType::Greater, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::from_bool(x > y))
-                    }
-                    (LiteralValue::Number(x), TokenType::GreaterEqual, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::from_bool(x >= y))
-                    }
-                    (LiteralValue::Number(x), TokenType::Less, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::from_bool(x < y))
-                    }
-                    (LiteralValue::Number(x), TokenType::LessEqual, LiteralValue::Number(y)) => {
-                        Ok(LiteralValue::from_bool(x <= y))
-                    }
-                    (LiteralValue::StringValue(_), op, LiteralValue::Number(_)) => {
-                        Err(format!("{} is not defined string and number", op))
-                    }
-                    (LiteralValue::Number(_), op, LiteralValue::StringValue(_)) => {
-                        Err(format!("{} is not defined string and number", op))
-                    }
-                    (
-                        LiteralValue::StringValue(s1),
-                        TokenType::Plus,
-                        LiteralValue::StringValue(s2),
-                    ) => Ok(LiteralValue::StringValue(format!("{}{}", s1, s2))),
-                    (x, TokenType::BangEqual, y) => Ok(LiteralValue::from_bool(x != y)),
-                    (x, TokenType::EqualEqual, y) => Ok(LiteralValue::from_bool(x == y)),
-                    (
-                        LiteralValue::StringValue(s1),
-                        TokenType::Greater,
-                        LiteralValue::StringValue(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 > s2)),
-                    (
-                        LiteralValue::StringValue(s1),
-                        TokenType::GreaterEqual,
-                        LiteralValue::StringValue(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 >= s2)),
-                    (
-                        LiteralValue::StringValue(s1),
-                        TokenType::Less,
-                        LiteralValue::StringValue(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 < s2)),
-                    (
-                        LiteralValue::StringValue(s1),
-                        TokenType::LessEqual,
-                        LiteralValue::StringValue(s2),
-                    ) => Ok(LiteralValue::from_bool(s1 <= s2)),
-                    (x, ttype, y) => Err(format!(
-                        "{} is not implemented for operands {:?} and {:?}",
-                        ttype, x, y
-                    )),
+                let left = left.evaluate(interpreter)?;
+                let right = right.evaluate(interpreter)?;
+                let value = apply_binary(&left, operator.token_type, &right)?;
+                if let LiteralValue::StringValue(_) = value {
+                    interpreter.budget.check_value(&value)?;
                 }
+                Ok(value)
             }
         }
     }
@@ -481,6 +641,104 @@ impl Expr {
     }
 }
 
+/// Applies a unary operator to an already evaluated operand.
+pub fn apply_unary(operator: TokenType, right: &LiteralValue) -> Result<LiteralValue, String> {
+    match (right, operator) {
+        (LiteralValue::Number(x), TokenType::Minus) => Ok(LiteralValue::Number(-x)),
+        (_, TokenType::Minus) => {
+            Err(format!("Minus not implemented for {}", right.to_type()))
+        }
+        (any, TokenType::Bang) => Ok(any.is_falsy()),
+        (_, ttype) => Err(format!("{} is not a valid unary operator", ttype)),
+    }
+}
+
+/// Applies a binary operator to already evaluated operands. Shared by the
+/// interpreter and the optimizer's constant folding.
+pub fn apply_binary(
+    left: &LiteralValue,
+    operator: TokenType,
+    right: &LiteralValue,
+) -> Result<LiteralValue, String> {
+    match (left, operator, right) {
+        (LiteralValue::Number(x), TokenType::Plus, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x + y))
+        }
+        (LiteralValue::Number(x), TokenType::Minus, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x - y))
+        }
+        (LiteralValue::Number(x), TokenType::Star, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x * y))
+        }
+        (LiteralValue::Number(x), TokenType::Slash, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Number(x / y))
+        }
+        (LiteralValue::Number(x), TokenType::Greater, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x > y))
+        }
+        (LiteralValue::Number(x), TokenType::GreaterEqual, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x >= y))
+        }
+        (LiteralValue::Number(x), TokenType::Less, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x < y))
+        }
+        (LiteralValue::Number(x), TokenType::LessEqual, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::from_bool(x <= y))
+        }
+        (LiteralValue::Number(x), TokenType::DotDot, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Range {
+                start: *x,
+                end: *y,
+                inclusive: false,
+            })
+        }
+        (LiteralValue::Number(x), TokenType::DotDotEqual, LiteralValue::Number(y)) => {
+            Ok(LiteralValue::Range {
+                start: *x,
+                end: *y,
+                inclusive: true,
+            })
+        }
+        (LiteralValue::StringValue(_), op, LiteralValue::Number(_)) => {
+            Err(format!("{} is not defined string and number", op))
+        }
+        (LiteralValue::Number(_), op, LiteralValue::StringValue(_)) => {
+            Err(format!("{} is not defined string and number", op))
+        }
+        (
+            LiteralValue::StringValue(s1),
+            TokenType::Plus,
+            LiteralValue::StringValue(s2),
+        ) => Ok(LiteralValue::StringValue(format!("{}{}", s1, s2))),
+        (x, TokenType::BangEqual, y) => Ok(LiteralValue::from_bool(x != y)),
+        (x, TokenType::EqualEqual, y) => Ok(LiteralValue::from_bool(x == y)),
+        (
+            LiteralValue::StringValue(s1),
+            TokenType::Greater,
+            LiteralValue::StringValue(s2),
+        ) => Ok(LiteralValue::from_bool(s1 > s2)),
+        (
+            LiteralValue::StringValue(s1),
+            TokenType::GreaterEqual,
+            LiteralValue::StringValue(s2),
+        ) => Ok(LiteralValue::from_bool(s1 >= s2)),
+        (
+            LiteralValue::StringValue(s1),
+            TokenType::Less,
+            LiteralValue::StringValue(s2),
+        ) => Ok(LiteralValue::from_bool(s1 < s2)),
+        (
+            LiteralValue::StringValue(s1),
+            TokenType::LessEqual,
+            LiteralValue::StringValue(s2),
+        ) => Ok(LiteralValue::from_bool(s1 <= s2)),
+        (x, ttype, y) => Err(format!(
+            "{} is not implemented for operands {:?} and {:?}",
+            ttype, x, y
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fmt::Binary;
@@ -495,6 +753,7 @@ mod tests {
             lexeme: "-".to_string(),
             literal: None,
             lineNumber: 0,
+            column: 0,
         };
         let onetwothree = Literal {
             value: LiteralValue::Number(123.0),
@@ -510,6 +769,7 @@ mod tests {
             lexeme: "*".to_string(),
             literal: None,
             lineNumber: 0,
+            column: 0,
         };
         let ast = Expr::Binary {
             left: Box::from(Unary {
@@ -523,4 +783,25 @@ mod tests {
         let result = ast.to_string();
         assert_eq!(result, "(* (- 123) (group 45.67))");
     }
+
+    #[test]
+    fn counts_long_ranges() {
+        let range = LiteralValue::Range {
+            start: 0.0,
+            end: 1e10,
+            inclusive: false,
+        };
+        assert_eq!(range.range_len(), 10_000_000_000);
+        assert_eq!(range.range_numbers().next_back(), Some(1e10 - 1.0));
+
+        let inclusive = LiteralValue::Range {
+            start: 1.5,
+            end: 4.0,
+            inclusive: true,
+        };
+        assert_eq!(
+            inclusive.range_numbers().collect::<Vec<_>>(),
+            vec![1.5, 2.5, 3.5]
+        );
+    }
 }
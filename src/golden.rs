@@ -0,0 +1,249 @@
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::optimizer;
+use crate::stmt::Stmt;
+use crate::testing::{self, TestOutcome};
+use std::cell::RefCell;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const EXPECT_OUTPUT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+/// What a `.bolt` case file says it should do, read from its
+/// `// expect: ...` and `// expect runtime error: ...` comments.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub runtime_error: Option<String>,
+}
+
+pub fn parse_expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+
+    for line in source.lines() {
+        if let Some(index) = line.find(EXPECT_OUTPUT) {
+            let expected = &line[index + EXPECT_OUTPUT.len()..];
+            expectations.output.push(expected.trim_end().to_string());
+        } else if let Some(index) = line.find(EXPECT_RUNTIME_ERROR) {
+            let expected = &line[index + EXPECT_RUNTIME_ERROR.len()..];
+            expectations.runtime_error = Some(expected.trim_end().to_string());
+        }
+    }
+
+    expectations
+}
+
+pub struct CaseResult {
+    pub path: PathBuf,
+    pub failures: Vec<String>,
+    /// Outcomes of the file's `test` blocks. Files that declare tests are
+    /// run as unit tests instead of being compared against `expect` comments.
+    pub tests: Vec<TestOutcome>,
+    pub duration: Duration,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Collects every `.bolt` file under `paths`, which may name files or
+/// directories. The result is sorted so reports are stable.
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut found = vec![];
+    for path in paths {
+        collect_cases(path, &mut found)?;
+    }
+    found.sort();
+    Ok(found)
+}
+
+fn collect_cases(path: &Path, found: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let entries = fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            collect_cases(&entry.path(), found)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "bolt") {
+        found.push(path.to_path_buf());
+    } else if !path.exists() {
+        return Err(format!("{}: no such file or directory", path.display()));
+    }
+    Ok(())
+}
+
+/// Runs a single case in a fresh interpreter and compares what it printed
+/// and how it failed against the expectations written in the file.
+pub fn run_case(path: &Path) -> CaseResult {
+    let start = Instant::now();
+    let (failures, tests) = match fs::read_to_string(path) {
+        Ok(source) => {
+            match panic::catch_unwind(AssertUnwindSafe(|| check_source(&source))) {
+                Ok(checked) => checked,
+                Err(_) => (vec!["Interpreter panicked".to_string()], vec![]),
+            }
+        }
+        Err(e) => (vec![format!("Could not read file: {}", e)], vec![]),
+    };
+
+    CaseResult {
+        path: path.to_path_buf(),
+        failures,
+        tests,
+        duration: start.elapsed(),
+    }
+}
+
+fn check_source(source: &str) -> (Vec<String>, Vec<TestOutcome>) {
+    let (program, tests) = match testing::parse_tests(source) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            let failure = format!("Unexpected error: {}", err.message().trim_end());
+            return (vec![failure], vec![]);
+        }
+    };
+
+    if !tests.is_empty() {
+        let outcomes = testing::run_tests(&program, &tests);
+        let failures = outcomes
+            .iter()
+            .filter_map(|outcome| {
+                outcome.error.as_ref().map(|msg| {
+                    let line = outcome.failed_line.unwrap_or(outcome.line);
+                    format!("test \"{}\" (line {}): {}", outcome.name, line, msg)
+                })
+            })
+            .collect();
+        return (failures, outcomes);
+    }
+
+    (check_expectations(source, &optimizer::optimize(program)), vec![])
+}
+
+fn check_expectations(source: &str, program: &[Stmt]) -> Vec<String> {
+    let expectations = parse_expectations(source);
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+    let result = interpreter
+        .interpret(program.iter().collect())
+        .map_err(Error::Runtime);
+
+    let mut failures = vec![];
+    match (result, &expectations.runtime_error) {
+        (Ok(_), None) => (),
+        (Ok(_), Some(expected)) => failures.push(format!(
+            "Expected runtime error '{}' but the script finished",
+            expected
+        )),
+        (Err(Error::Runtime(msg)), Some(expected)) if msg == *expected => (),
+        (Err(Error::Runtime(msg)), Some(expected)) => failures.push(format!(
+            "Expected runtime error '{}' but got '{}'",
+            expected, msg
+        )),
+        (Err(err), _) => failures.push(format!("Unexpected error: {}", err.message().trim_end())),
+    }
+
+    let actual = String::from_utf8_lossy(&output.borrow()).to_string();
+    let actual: Vec<String> = actual.lines().map(|l| l.to_string()).collect();
+    if actual != expectations.output {
+        failures.push(format!("Output mismatch:\n{}", diff(&expectations.output, &actual)));
+    }
+
+    failures
+}
+
+/// Runs all `paths` on a pool of worker threads. Each case gets its own
+/// interpreter, so cases can't observe each other. Results come back in the
+/// same order as `paths`.
+pub fn run_cases(paths: &[PathBuf]) -> Vec<CaseResult> {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(paths.len())
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(paths.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= paths.len() {
+                    break;
+                }
+                let result = run_case(&paths[index]);
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Line diff of `expected` against `actual`: unchanged lines are prefixed
+/// with two spaces, missing lines with `- ` and unexpected ones with `+ `.
+pub fn diff(expected: &[String], actual: &[String]) -> String {
+    let (n, m) = (expected.len(), actual.len());
+    // lcs[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..].
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_expect_comments() {
+        let source = "print 1; // expect: 1\n// expect: \"two\"\nprint x; // expect runtime error: boom\n";
+        let expectations = parse_expectations(source);
+
+        assert_eq!(expectations.output, vec!["1", "\"two\""]);
+        assert_eq!(expectations.runtime_error, Some("boom".to_string()));
+    }
+
+    #[test]
+    fn diff_marks_missing_and_extra_lines() {
+        let expected: Vec<String> = vec!["1".into(), "2".into(), "3".into()];
+        let actual: Vec<String> = vec!["1".into(), "3".into(), "4".into()];
+
+        assert_eq!(diff(&expected, &actual), "  1\n- 2\n  3\n+ 4");
+    }
+}
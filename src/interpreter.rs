@@ -1,80 +1,407 @@
+use crate::collections;
 use crate::environment::{self, Environment};
-use crate::expr::{LiteralValue, Expr};
+use crate::error::Error;
+use crate::expr::{Arity, FunctionSource, LiteralValue, Expr};
+use crate::json::Json;
+use crate::debugger::DebugHook;
+use crate::native::NativeFunction;
+use crate::sandbox::{range_expansion_cost, Budget, CancellationToken, Config, ENVIRONMENT_COST};
 use crate::scanner::{Token, TokenType};
 use crate::stmt::Stmt;
 use std::cell::{Ref, RefCell};
+use std::io::{self, Write};
 use std::ops::Deref;
 use std::rc::{self, Rc};
 use std::time::SystemTime;
 use std::vec;
 
+#[derive(Clone)]
 pub struct Interpreter {
     pub specials: Rc<RefCell<Environment>>,
     pub environment: Rc<RefCell<Environment>>,
+    pub output: Rc<RefCell<dyn Write>>,
+    pub errors: Rc<RefCell<dyn Write>>,
+    pub budget: Rc<Budget>,
+    pub cancellation: CancellationToken,
+    pub debug_hook: Option<Rc<RefCell<dyn DebugHook>>>,
 }
 
-fn clock_impl(_args: &Vec<LiteralValue>) -> LiteralValue {
+fn clock_impl() -> Result<f64, String> {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .expect("Could not get system time")
+        .map_err(|_| "Could not get system time".to_string())?
         .as_millis();
 
-    LiteralValue::Number(now as f64 / 1000.0)
+    Ok(now as f64 / 1000.0)
+}
+
+fn read_file_impl(path: String) -> Result<String, String> {
+    std::fs::read_to_string(&path).map_err(|e| format!("Could not read file '{}': {}", path, e))
+}
+
+fn write_file_impl(path: String, contents: String) -> Result<(), String> {
+    std::fs::write(&path, contents).map_err(|e| format!("Could not write file '{}': {}", path, e))
+}
+
+fn assert_impl(condition: LiteralValue, msg: String) -> Result<(), String> {
+    if condition.is_truthy() == LiteralValue::True {
+        Ok(())
+    } else {
+        Err(format!("Assertion failed: {}", msg))
+    }
+}
+
+fn assert_eq_impl(left: LiteralValue, right: LiteralValue) -> Result<(), String> {
+    if left == right {
+        Ok(())
+    } else {
+        Err(format!(
+            "Assertion failed: {} != {}",
+            left.to_string(),
+            right.to_string()
+        ))
+    }
+}
+
+fn json_parse_impl(text: String) -> Result<LiteralValue, String> {
+    Json::parse(&text)
+        .map(|json| json.to_value())
+        .map_err(|e| format!("json_parse: {}", e.to_string()))
+}
+
+fn json_stringify_impl(value: LiteralValue, indent: Option<f64>) -> Result<String, String> {
+    let indent = match indent {
+        None => 0,
+        Some(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        Some(n) => {
+            return Err(format!(
+                "json_stringify: indent must be a whole number, got {}",
+                n
+            ))
+        }
+    };
+    Json::from_value(&value)
+        .and_then(|json| json.try_stringify(indent))
+        .map_err(|msg| format!("json_stringify: {}", msg))
+}
+
+fn len_impl(value: LiteralValue) -> Result<f64, String> {
+    match value {
+        LiteralValue::StringValue(s) => Ok(s.chars().count() as f64),
+        LiteralValue::Array(items) => Ok(items.len() as f64),
+        LiteralValue::Object(members) => Ok(members.len() as f64),
+        other => Err(format!(
+            "len expected a String, Array or Object but got {}",
+            other.to_type()
+        )),
+    }
+}
+
+/// Looks up an array element by index or an object member by key. Missing
+/// entries are `nil`.
+fn get_impl(collection: LiteralValue, key: LiteralValue) -> Result<LiteralValue, String> {
+    match (&collection, &key) {
+        (LiteralValue::Array(items), LiteralValue::Number(index)) => {
+            if index.fract() != 0.0 {
+                return Err(format!(
+                    "get expected a whole number index but got {}",
+                    index
+                ));
+            }
+            let item = (*index >= 0.0)
+                .then(|| items.get(*index as usize))
+                .flatten();
+            Ok(item.cloned().unwrap_or(LiteralValue::Nil))
+        }
+        (LiteralValue::Object(members), LiteralValue::StringValue(name)) => Ok(members
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, value)| value.clone())
+            .unwrap_or(LiteralValue::Nil)),
+        (LiteralValue::Array(_), other) | (LiteralValue::Object(_), other) => Err(format!(
+            "Can't index {} with {}",
+            collection.to_type(),
+            other.to_type()
+        )),
+        (other, _) => Err(format!(
+            "get expected an Array or Object but got {}",
+            other.to_type()
+        )),
+    }
+}
+
+/// A copy of an array with the element at `index` replaced, or a copy of an
+/// object with member `key` set, added at the end if it is new.
+fn set_impl(
+    collection: LiteralValue,
+    key: LiteralValue,
+    value: LiteralValue,
+) -> Result<LiteralValue, String> {
+    match (&collection, &key) {
+        (LiteralValue::Array(items), LiteralValue::Number(index)) => {
+            if index.fract() != 0.0 || *index < 0.0 || *index as usize >= items.len() {
+                return Err(format!(
+                    "set index {} is out of bounds for an Array of length {}",
+                    index,
+                    items.len()
+                ));
+            }
+            let mut items = items.as_ref().clone();
+            items[*index as usize] = value;
+            Ok(LiteralValue::Array(Rc::new(items)))
+        }
+        (LiteralValue::Object(members), LiteralValue::StringValue(name)) => {
+            let mut members = members.as_ref().clone();
+            match members.iter_mut().find(|(k, _)| k == name) {
+                Some(member) => member.1 = value,
+                None => members.push((name.clone(), value)),
+            }
+            Ok(LiteralValue::Object(Rc::new(members)))
+        }
+        (LiteralValue::Array(_), other) | (LiteralValue::Object(_), other) => Err(format!(
+            "Can't index {} with {}",
+            collection.to_type(),
+            other.to_type()
+        )),
+        (other, _) => Err(format!(
+            "set expected an Array or Object but got {}",
+            other.to_type()
+        )),
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let mut env = Environment::new();
-        env.define(
-            "clock".to_string(),
-            LiteralValue::Callable {
-                name: "clock".to_string(),
-                arity: 0,
-                fun: Rc::new(clock_impl),
-            },
-        );
-        Self {
+        Self::with_output(
+            Rc::new(RefCell::new(io::stdout())),
+            Rc::new(RefCell::new(io::stderr())),
+        )
+    }
+
+    /// Creates an interpreter whose `print` statements write to `output` and
+    /// whose reported errors go to `errors` instead of stdout/stderr.
+    pub fn with_output(output: Rc<RefCell<dyn Write>>, errors: Rc<RefCell<dyn Write>>) -> Self {
+        Self::with_config(output, errors, Config::default())
+    }
+
+    /// Creates an interpreter that enforces the limits in `config` and only
+    /// registers the natives its capabilities allow.
+    pub fn with_config(
+        output: Rc<RefCell<dyn Write>>,
+        errors: Rc<RefCell<dyn Write>>,
+        config: Config,
+    ) -> Self {
+        let capabilities = config.capabilities;
+        let mut interpreter = Self {
             specials: Rc::new(RefCell::new(Environment::new())),
-            environment: Rc::new(RefCell::new(env)),
+            environment: Rc::new(RefCell::new(Environment::new())),
+            output,
+            errors,
+            budget: Rc::new(Budget::new(config)),
+            cancellation: CancellationToken::new(),
+            debug_hook: None,
+        };
+        if capabilities.clock {
+            interpreter.register_fn("clock", clock_impl);
+        }
+        if capabilities.file_io {
+            interpreter.register_fn("read_file", read_file_impl);
+            interpreter.register_fn("write_file", write_file_impl);
+        }
+        interpreter.register_fn("assert", assert_impl);
+        interpreter.register_fn("assert_eq", assert_eq_impl);
+        interpreter.register_fn("json_parse", json_parse_impl);
+        interpreter.register_fn("json_stringify", json_stringify_impl);
+        interpreter.register_fn("len", len_impl);
+        interpreter.register_fn("get", get_impl);
+        interpreter.register_fn("set", set_impl);
+        collections::register(&mut interpreter);
+        interpreter
+    }
+
+    /// Exposes a Rust closure to scripts as a global function. Arguments are
+    /// converted with `FromValue` and checked against the closure's
+    /// signature before it is called.
+    pub fn register_fn<Args, F>(&mut self, name: &str, fun: F)
+    where
+        F: NativeFunction<Args> + 'static,
+    {
+        let fun_name = name.to_string();
+        let budget = self.budget.clone();
+        let callable = LiteralValue::Callable {
+            name: name.to_string(),
+            arity: Arity::exact(fun.arity()),
+            fun: Rc::new(
+                move |args: &Vec<LiteralValue>, _: &[(String, LiteralValue)]| {
+                    // Ranges become arrays when a native takes them, so make sure
+                    // they fit in the memory budget before that happens.
+                    let expansion = args.iter().fold(0, |total: usize, arg| {
+                        total.saturating_add(range_expansion_cost(arg))
+                    });
+                    budget.ensure_available(expansion)?;
+                    fun.invoke(&fun_name, args)
+                },
+            ),
+            source: None,
+        };
+        self.environment
+            .borrow_mut()
+            .define_top_level(name.to_string(), callable);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LiteralValue> {
+        self.environment.borrow().get(name)
+    }
+
+    /// Calls a function defined by a script (or registered natively) from Rust.
+    pub fn call_fn(&self, name: &str, args: Vec<LiteralValue>) -> Result<LiteralValue, String> {
+        match self.get_global(name) {
+            Some(callable) => callable.call(&args),
+            None => Err(format!("Function '{}' has not been declared", name)),
         }
     }
 
-    fn for_closure(parent: Rc<RefCell<Environment>>) -> Self {
+    fn for_closure(parent: &Interpreter) -> Self {
         let environment = Rc::new(RefCell::new(Environment::new()));
-        environment.borrow_mut().enclosing = Some(parent);
+        environment.borrow_mut().enclosing = Some(parent.environment.clone());
 
         Self {
             specials: Rc::new(RefCell::new(Environment::new())),
             environment,
+            output: parent.output.clone(),
+            errors: parent.errors.clone(),
+            budget: parent.budget.clone(),
+            cancellation: parent.cancellation.clone(),
+            debug_hook: parent.debug_hook.clone(),
         }
     }
 
-    pub fn for_anon(parent: Rc<RefCell<Environment>>) -> Self {
+    pub fn for_anon(parent: &Interpreter) -> Self {
         let mut env = Environment::new();
-        env.enclosing = Some(parent);
+        env.enclosing = Some(parent.environment.clone());
         Self {
             specials: Rc::new(RefCell::new(Environment::new())),
             environment: Rc::new(RefCell::new(env)),
+            output: parent.output.clone(),
+            errors: parent.errors.clone(),
+            budget: parent.budget.clone(),
+            cancellation: parent.cancellation.clone(),
+            debug_hook: parent.debug_hook.clone(),
+        }
+    }
+
+    /// Installs a hook that is called before every statement and around
+    /// every function call, e.g. a debugger.
+    pub fn set_debug_hook(&mut self, hook: Rc<RefCell<dyn DebugHook>>) {
+        self.debug_hook = Some(hook);
+    }
+
+    /// Runs the body of a called function in this (fresh) interpreter and
+    /// returns the value of the first `return` reached, or `nil`. `line` is
+    /// where the function is declared, for the debug hook.
+    pub fn run_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        body: &[Box<Stmt>],
+    ) -> Result<LiteralValue, String> {
+        self.budget.enter_call()?;
+        let result = self.run_body(name, line, args, body);
+        self.budget.exit_call();
+        result
+    }
+
+    fn run_body(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        body: &[Box<Stmt>],
+    ) -> Result<LiteralValue, String> {
+        if let Some(hook) = self.debug_hook.clone() {
+            hook.borrow_mut().enter_function(name, line, args, self)?;
+        }
+
+        let mut result = Ok(LiteralValue::Nil);
+        for stmt in body {
+            if let Err(msg) = self.interpret(vec![stmt.as_ref()]) {
+                result = Err(msg);
+                break;
+            }
+            if let Some(value) = self.specials.borrow_mut().get("return") {
+                result = Ok(value);
+                break;
+            }
+        }
+
+        if let Some(hook) = self.debug_hook.clone() {
+            hook.borrow_mut().exit_function(&result, self);
+        }
+        result
+    }
+
+    pub fn report_error(&self, msg: &str) -> Result<(), String> {
+        writeln!(self.errors.borrow_mut(), "{}", msg).map_err(|e| e.to_string())
+    }
+
+    /// Runs a program from the top: the sandbox budget starts over, and a
+    /// failure is reported as `Error::Limit` if a limit stopped the script.
+    pub fn execute(&mut self, stmts: Vec<&Stmt>) -> Result<(), Error> {
+        self.budget.reset();
+        self.interpret(stmts).map_err(|msg| self.runtime_error(msg))
+    }
+
+    /// A handle that stops this interpreter (and its closures) when
+    /// cancelled, from any thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Tags a runtime error message with what stopped the script: a
+    /// cancellation, a sandbox limit or an ordinary error.
+    pub fn runtime_error(&self, msg: String) -> Error {
+        if self.cancellation.is_cancelled() {
+            return Error::Interrupted(msg);
+        }
+        match self.budget.exceeded() {
+            Some(limit) => Error::Limit(limit, msg),
+            None => Error::Runtime(msg),
         }
     }
 
     pub fn interpret(&mut self, stmts: Vec<&Stmt>) -> Result<(), String> {
         for stmt in stmts {
+            self.budget.step()?;
+            if let Some(hook) = self.debug_hook.clone() {
+                hook.borrow_mut().before_stmt(stmt, self)?;
+            }
             match stmt {
                 Stmt::Expression { expression } => {
-                    expression.evaluate(self.environment.clone())?;
+                    expression.evaluate(self)?;
                 }
-                Stmt::Print { expression } => {
-                    let value = expression.evaluate(self.environment.clone())?;
-                    println!("{}", value.to_string());
+                Stmt::Print { expression, .. } => {
+                    let value = expression.evaluate(self)?;
+                    writeln!(self.output.borrow_mut(), "{}", value.to_string())
+                        .map_err(|e| e.to_string())?;
                 }
-                Stmt::Var { name, initializer } => {
-                    let value = initializer.evaluate(self.environment.clone())?;
+                Stmt::Var {
+                    name, initializer, ..
+                } => {
+                    let value = initializer.evaluate(self)?;
+                    self.budget.check_value(&value)?;
                     self.environment
                         .borrow_mut()
                         .define(name.lexeme.to_string(), value);
                 }
                 Stmt::Block { statements } => {
+                    self.budget.allocate(ENVIRONMENT_COST)?;
                     let mut new_environment = Environment::new();
                     new_environment.enclosing = Some(self.environment.clone());
 
@@ -87,12 +414,17 @@ impl Interpreter {
                     block_result?;
                 }
                 Stmt::IfStmt {
+                    keyword,
                     predicate,
                     then,
                     els,
                 } => {
-                    let truth_value = predicate.evaluate(self.environment.clone())?;
-                    if truth_value.is_truthy() == LiteralValue::True {
+                    let truth_value = predicate.evaluate(self)?;
+                    let taken = truth_value.is_truthy() == LiteralValue::True;
+                    if let Some(hook) = self.debug_hook.clone() {
+                        hook.borrow_mut().branch(keyword.lineNumber, taken);
+                    }
+                    if taken {
                         let statements = vec![then.as_ref()];
                         self.interpret(statements)?;
                     } else if let Some(els_stmt) = els {
@@ -100,76 +432,47 @@ impl Interpreter {
                         self.interpret(statements)?;
                     }
                 }
-                Stmt::WhileStmt { condition, body } => {
-                    let mut flag = condition.evaluate(self.environment.clone())?;
+                Stmt::WhileStmt {
+                    keyword,
+                    condition,
+                    body,
+                } => {
+                    let mut flag = condition.evaluate(self)?;
 
                     while flag.is_truthy() == LiteralValue::True {
+                        self.cancellation.check(keyword.lineNumber)?;
                         let statements = vec![body.as_ref()];
                         self.interpret(statements)?;
-                        flag = condition.evaluate(self.environment.clone())?;
+                        flag = condition.evaluate(self)?;
                     }
                 }
-                Stmt::Function { name, params, body } => {
-                    let arity = params.len();
-
-                    let params: Vec<Token> = params.iter().map(|t| (*t).clone()).collect();
-                    let body: Vec<Box<Stmt>> = body.iter().map(|b| (*b).clone()).collect();
-                    let name_clone = name.lexeme.clone();
-                    let parent_env = self.environment.clone();
-                    let fun_impl = move |args: &Vec<LiteralValue>| {
-                        let mut clos_int = Interpreter::for_closure(parent_env.clone());
-
-                        // let n = params.clone();
-                        for (i, arg) in args.iter().enumerate() {
-                            // let name_clone = n[i].lexeme.clone();
-                            clos_int
-                                .environment
-                                .borrow_mut()
-                                .define(params[i].lexeme.clone(), (*arg).clone());
-                        }
-                        for i in 0..(body.len()) {
-                            clos_int
-                                .interpret(vec![body[i].as_ref()])
-                                .expect(&format!("Evaluating a failed inside {}", name_clone));
-
-                            if let Some(value) = clos_int.specials.borrow_mut().get("return") {
-                                return value;
-                            }
-                        }
-                        LiteralValue::Nil
-
-                        //     if let Stmt::ReturnStmt {
-                        //         keyword: _,
-                        //         value: _,
-                        //     } = *body[i]
-                        //     {
-                        //         let value = clos_int
-                        //             .environment
-                        //             .borrow_mut()
-                        //             .get("return")
-                        //             .expect("return value was not defined in the environment even though return statememnt was intereted");
-                        //         return value;
-                        //     }
-                        // }
-
-                        // let value;
-                        // match body[body.len() - 1].as_ref() {
-                        //     Stmt::Expression { expression } => {
-                        //         value = expression.evaluate(clos_int.environment.clone()).unwrap()
-                        //     }
-                        //     // _ => todo!("Didn't get and expression"),
-                        //     _ => todo!("Didn't get and expression"),
-                        // }
-
-                        // value
-                    };
-
-                    let callable = LiteralValue::Callable {
-                        name: name.lexeme.clone(),
-                        arity,
-                        fun: Rc::new(fun_impl),
-                    };
+                Stmt::ForIn {
+                    keyword,
+                    variable,
+                    iterable,
+                    body,
+                } => {
+                    let iterable = iterable.evaluate(self)?;
+                    for item in Self::iterate(iterable)? {
+                        self.cancellation.check(keyword.lineNumber)?;
+                        self.budget.allocate(ENVIRONMENT_COST)?;
+                        let mut new_environment = Environment::new();
+                        new_environment.enclosing = Some(self.environment.clone());
+                        new_environment.define(variable.lexeme.clone(), item?);
+
+                        let old_environment = self.environment.clone();
+                        self.environment = Rc::new(RefCell::new(new_environment));
+                        let body_result = self.interpret(vec![body.as_ref()]);
+                        self.environment = old_environment;
 
+                        body_result?;
+                        if self.specials.borrow().get("return").is_some() {
+                            break;
+                        }
+                    }
+                }
+                Stmt::Function { name, .. } => {
+                    let callable = self.function_value(stmt)?;
                     self.environment
                         .borrow_mut()
                         .define(name.lexeme.clone(), callable);
@@ -177,7 +480,7 @@ impl Interpreter {
                 Stmt::ReturnStmt { keyword, value } => {
                     let evaL_val;
                     if let Some(value) = value {
-                        evaL_val = value.evaluate(self.environment.clone())?;
+                        evaL_val = value.evaluate(self)?;
                     } else {
                         evaL_val = LiteralValue::Nil;
                     }
@@ -191,7 +494,149 @@ impl Interpreter {
         Ok(())
     }
 
+    /// The items a `for ... in` loop goes through: the characters of a
+    /// string, the elements of an array, the numbers of a range, or, for an
+    /// iterator object with a `next` function or a bare function, the values
+    /// it returns on each call until it returns `nil`.
+    fn iterate(
+        iterable: LiteralValue,
+    ) -> Result<Box<dyn Iterator<Item = Result<LiteralValue, String>>>, String> {
+        Ok(match iterable {
+            LiteralValue::StringValue(s) => Box::new(
+                s.chars()
+                    .map(|c| Ok(LiteralValue::StringValue(c.to_string())))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            LiteralValue::Array(items) => Box::new(items.as_ref().clone().into_iter().map(Ok)),
+            LiteralValue::Range { .. } => Box::new(
+                iterable
+                    .range_numbers()
+                    .map(|n| Ok(LiteralValue::Number(n))),
+            ),
+            LiteralValue::Object(members) => match members.iter().find(|(k, _)| k == "next") {
+                Some((_, next @ LiteralValue::Callable { .. })) => Self::iterate(next.clone())?,
+                _ => return Err("Can't iterate over Object without a 'next' function".to_string()),
+            },
+            LiteralValue::Callable { .. } => {
+                Box::new(std::iter::from_fn(move || match iterable.call(&vec![]) {
+                    Ok(LiteralValue::Nil) => None,
+                    result => Some(result),
+                }))
+            }
+            other => return Err(format!("Can't iterate over {}", other.to_type())),
+        })
+    }
+
+    /// Builds the value for a `fun` declaration, closing over this
+    /// interpreter's environment.
+    pub fn function_value(&self, declaration: &Stmt) -> Result<LiteralValue, String> {
+        let Stmt::Function {
+            name,
+            params,
+            defaults,
+            rest,
+            body,
+            ..
+        } = declaration
+        else {
+            return Err("Expected a function declaration".to_string());
+        };
+        let arity = Arity::of(defaults, *rest);
+
+        let params: Vec<Token> = params.iter().map(|t| (*t).clone()).collect();
+        let defaults = defaults.clone();
+        let rest = *rest;
+        let body: Vec<Box<Stmt>> = body.iter().map(|b| (*b).clone()).collect();
+        let host = self.clone();
+        let fun_name = name.lexeme.clone();
+        let line = name.lineNumber;
+        let fun_impl = move |args: &Vec<LiteralValue>, named: &[(String, LiteralValue)]| {
+            host.budget.allocate(ENVIRONMENT_COST)?;
+            let mut clos_int = Interpreter::for_closure(&host);
+            let args = clos_int.bind_arguments(&fun_name, &params, &defaults, rest, args, named)?;
+            clos_int.run_function(&fun_name, line, &args, &body)
+        };
+
+        Ok(LiteralValue::Callable {
+            name: name.lexeme.clone(),
+            arity,
+            fun: Rc::new(fun_impl),
+            source: Some(Rc::new(FunctionSource {
+                declaration: declaration.clone(),
+                closure: self.environment.clone(),
+            })),
+        })
+    }
+
+    /// Defines a called function's parameters in this (fresh) interpreter's
+    /// environment and returns their values in order. Parameters that
+    /// weren't passed get their default, which may refer to earlier
+    /// parameters, and a rest parameter collects the extra positional
+    /// arguments into an array.
+    pub fn bind_arguments(
+        &mut self,
+        name: &str,
+        params: &[Token],
+        defaults: &[Option<Expr>],
+        rest: bool,
+        args: &[LiteralValue],
+        named: &[(String, LiteralValue)],
+    ) -> Result<Vec<LiteralValue>, String> {
+        let fixed = params.len() - rest as usize;
+        let mut passed: Vec<Option<LiteralValue>> = vec![None; fixed];
+        for (slot, arg) in passed.iter_mut().zip(args) {
+            *slot = Some(arg.clone());
+        }
+        for (arg_name, value) in named {
+            match params[..fixed].iter().position(|p| p.lexeme == *arg_name) {
+                Some(i) if passed[i].is_some() => {
+                    return Err(format!(
+                        "Callable {} got argument '{}' twice",
+                        name, arg_name
+                    ))
+                }
+                Some(i) => passed[i] = Some(value.clone()),
+                None => {
+                    return Err(format!(
+                        "Callable {} has no parameter named '{}'",
+                        name, arg_name
+                    ))
+                }
+            }
+        }
+
+        let mut values = vec![];
+        for ((param, default), value) in params.iter().zip(defaults).zip(passed) {
+            let value = match (value, default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => default.evaluate(self)?,
+                (None, None) => {
+                    return Err(format!(
+                        "Callable {} is missing argument '{}'",
+                        name, param.lexeme
+                    ))
+                }
+            };
+            self.environment
+                .borrow_mut()
+                .define(param.lexeme.clone(), value.clone());
+            values.push(value);
+        }
+        if rest {
+            let extra = args.get(fixed..).unwrap_or_default().to_vec();
+            let value = LiteralValue::Array(Rc::new(extra));
+            self.environment
+                .borrow_mut()
+                .define(params[fixed].lexeme.clone(), value.clone());
+            values.push(value);
+        }
+        Ok(values)
+    }
+
     pub fn resolve(&mut self, expr: &Expr, _steps: usize) -> Result<(), String> {
-        todo!()
+        // Variables are looked up dynamically through the environment chain,
+        // so there is nothing to record yet.
+        Ok(())
     }
 }
@@ -0,0 +1,642 @@
+use crate::expr::LiteralValue;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// How deeply arrays and objects may nest in parsed text. The parser is
+/// recursive, so this keeps untrusted input from overflowing the stack.
+pub const MAX_DEPTH: usize = 512;
+
+/// The widest indent `stringify` uses, as in JavaScript's `JSON.stringify`.
+pub const MAX_INDENT: usize = 10;
+
+/// A parsed JSON document. Object members keep their source order so that
+/// values round-trip without reshuffling keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl JsonError {
+    pub fn to_string(&self) -> String {
+        format!(
+            "Line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser::new(text);
+        parser.skip_whitespace();
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            return Err(parser.error("Unexpected trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Builds an object from `(key, value)` pairs.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|x| *x >= 0.0 && x.fract() == 0.0)
+            .map(|x| x as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Converts parsed JSON into a Bolt value.
+    pub fn to_value(&self) -> LiteralValue {
+        match self {
+            Json::Null => LiteralValue::Nil,
+            Json::Bool(b) => LiteralValue::from_bool(*b),
+            Json::Number(x) => LiteralValue::Number(*x),
+            Json::String(s) => LiteralValue::StringValue(s.clone()),
+            Json::Array(items) => {
+                LiteralValue::Array(Rc::new(items.iter().map(Json::to_value).collect()))
+            }
+            Json::Object(members) => LiteralValue::Object(Rc::new(
+                members
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.to_value()))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Converts a Bolt value into JSON. Ranges become arrays of their numbers;
+    /// functions have no JSON form.
+    pub fn from_value(value: &LiteralValue) -> Result<Json, String> {
+        Ok(match value {
+            LiteralValue::Nil => Json::Null,
+            LiteralValue::True => Json::Bool(true),
+            LiteralValue::False => Json::Bool(false),
+            LiteralValue::Number(x) => Json::Number(*x),
+            LiteralValue::StringValue(s) => Json::String(s.clone()),
+            LiteralValue::Array(items) => Json::Array(
+                items
+                    .iter()
+                    .map(Json::from_value)
+                    .collect::<Result<Vec<Json>, String>>()?,
+            ),
+            LiteralValue::Object(members) => Json::Object(
+                members
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), Json::from_value(value)?)))
+                    .collect::<Result<Vec<(String, Json)>, String>>()?,
+            ),
+            LiteralValue::Range { .. } => {
+                Json::Array(value.range_numbers().map(Json::Number).collect())
+            }
+            LiteralValue::Callable { name, .. } => {
+                return Err(format!("Function '{}' can't be converted to JSON", name))
+            }
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    pub fn to_string(&self) -> String {
+        self.stringify(0)
+    }
+
+    /// Serializes the value. An `indent` of zero produces compact output,
+    /// anything else pretty-prints with that many spaces per level, up to
+    /// `MAX_INDENT`.
+    pub fn stringify(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.write(&mut out, indent.min(MAX_INDENT), 0);
+        out
+    }
+
+    /// Like `stringify`, but fails on an indent wider than `MAX_INDENT`
+    /// instead of narrowing it, for indents that come from scripts.
+    pub fn try_stringify(&self, indent: usize) -> Result<String, String> {
+        if indent > MAX_INDENT {
+            return Err(format!("indent can't be more than {}", MAX_INDENT));
+        }
+        Ok(self.stringify(indent))
+    }
+
+    fn write(&self, out: &mut String, indent: usize, depth: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(x) => {
+                if x.is_finite() {
+                    out.push_str(&x.to_string())
+                } else {
+                    out.push_str("null")
+                }
+            }
+            Json::String(s) => write_string(out, s),
+            Json::Array(items) => {
+                if items.is_empty() {
+                    out.push_str("[]");
+                    return;
+                }
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, indent, depth + 1);
+                    item.write(out, indent, depth + 1);
+                }
+                newline(out, indent, depth);
+                out.push(']');
+            }
+            Json::Object(members) => {
+                if members.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, indent, depth + 1);
+                    write_string(out, key);
+                    out.push(':');
+                    if indent > 0 {
+                        out.push(' ');
+                    }
+                    value.write(out, indent, depth + 1);
+                }
+                newline(out, indent, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn newline(out: &mut String, indent: usize, depth: usize) {
+    if indent > 0 {
+        out.push('\n');
+        if let Some(width) = indent.checked_mul(depth) {
+            out.push_str(&" ".repeat(width));
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(x: f64) -> Self {
+        Json::Number(x)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(x: usize) -> Self {
+        Json::Number(x as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Json::Null,
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl JsonParser {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            current: 0,
+            line: 1,
+            column: 1,
+            depth: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char, message: &str) -> Result<(), JsonError> {
+        if self.peek() == Some(expected) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.advance();
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        match self.peek() {
+            None => Err(self.error("Unexpected end of input")),
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(self.error(&format!("Unexpected character '{}'", c))),
+        }
+    }
+
+    /// Parses an array or object one level deeper, failing past `MAX_DEPTH`.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("Nesting deeper than {} levels", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("Invalid literal, expected '{}'", word)));
+            }
+            self.advance();
+        }
+        Ok(value)
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.advance();
+        let mut members: Vec<(String, Json)> = vec![];
+        let mut seen = HashMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.advance();
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected string key in object"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':', "Expected ':' after object key")?;
+            self.skip_whitespace();
+            let value = self.value()?;
+
+            // Later duplicates win, as in most JSON implementations.
+            match seen.get(&key) {
+                Some(&index) => members[index] = (key, value),
+                None => {
+                    seen.insert(key.clone(), members.len());
+                    members.push((key, value));
+                }
+            }
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some('}') => {
+                    self.advance();
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("Expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.advance();
+        let mut items = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.advance();
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some(']') => {
+                    self.advance();
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.advance();
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                None => return Err(self.error("Unterminated string")),
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    };
+                    s.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("Control character in string"))
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid unicode escape"))?;
+            self.advance();
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let code = self.hex4()?;
+        if (0xD800..0xDC00).contains(&code) {
+            if self.advance() != Some('\\') || self.advance() != Some('u') {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            let combined = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+            return char::from_u32(combined).ok_or_else(|| self.error("Invalid unicode escape"));
+        }
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.current;
+        let (line, column) = (self.line, self.column);
+
+        if self.peek() == Some('-') {
+            self.advance();
+        }
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Err(self.error("Expected digit"));
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+        }
+        if self.peek() == Some('.') {
+            self.advance();
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("Expected digit after decimal point"));
+            }
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.advance();
+            }
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.advance();
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.advance();
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("Expected digit in exponent"));
+            }
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.advance();
+            }
+        }
+
+        let text: String = self.chars[start..self.current].iter().collect();
+        text.parse::<f64>().map(Json::Number).map_err(|_| JsonError {
+            line,
+            column,
+            message: format!("Invalid number '{}'", text),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_nested_values() {
+        let text = r#"{"name":"bolt","tags":["a","b\n"],"version":1.5,"ok":true,"none":null}"#;
+        let value = Json::parse(text).unwrap();
+
+        assert_eq!(value.get("name").and_then(|v| v.as_str()), Some("bolt"));
+        assert_eq!(value.to_string(), text);
+    }
+
+    #[test]
+    fn pretty_prints_with_indent() {
+        let value = Json::parse(r#"{"a":[1,2],"b":{}}"#).unwrap();
+        assert_eq!(value.stringify(2), "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}");
+    }
+
+    #[test]
+    fn rejects_huge_indents() {
+        let value = Json::parse("[[1]]").unwrap();
+        assert_eq!(
+            value.try_stringify(usize::MAX),
+            Err("indent can't be more than 10".to_string())
+        );
+        assert_eq!(
+            value.try_stringify(MAX_INDENT),
+            Ok(value.stringify(MAX_INDENT))
+        );
+        assert_eq!(value.stringify(4_000_000_000), value.stringify(MAX_INDENT));
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let err = Json::parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 7));
+        assert_eq!(err.message, "Expected ':' after object key");
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&deep).is_ok());
+
+        let too_deep = format!("{{\"a\":\n{}", "[".repeat(20_000));
+        let err = Json::parse(&too_deep).unwrap_err();
+        assert_eq!((err.line, err.column), (2, MAX_DEPTH));
+        assert_eq!(err.message, "Nesting deeper than 512 levels");
+    }
+
+    #[test]
+    fn converts_to_and_from_bolt_values() {
+        let text = r#"{"name":"bolt","tags":["a",2,null],"nested":{"ok":true}}"#;
+        let value = Json::parse(text).unwrap().to_value();
+
+        assert_eq!(
+            value.to_string(),
+            r#"{"name": "bolt", "tags": ["a", 2, nil], "nested": {"ok": true}}"#
+        );
+        assert_eq!(Json::from_value(&value).unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn decodes_unicode_escapes() {
+        let value = Json::parse(r#""\u00e9\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("é😀"));
+    }
+}
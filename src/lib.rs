@@ -0,0 +1,129 @@
+//! Bolt is a small tree-walking interpreter.
+//!
+//! The quickest way to run a script is [`eval`], which returns the value of
+//! the last expression statement:
+//!
+//! ```
+//! let value = bolt::eval("var a = 20; a + 22;").unwrap();
+//! assert_eq!(value, bolt::Value::Number(42.0));
+//! ```
+//!
+//! Hosts that need more control (output capture, native functions, keeping
+//! state between runs) create an [`Interpreter`] and use [`run`] or
+//! [`eval_with`]. The pipeline stages — [`Scanner`], [`Parser`], [`Resolver`]
+//! and [`Interpreter`] — are also exposed individually.
+
+#![allow(dead_code)]
+#![allow(non_snake_case)]
+#![allow(unused_variables)]
+#![allow(unused_imports)]
+#![allow(clippy::vec_box)]
+#![allow(clippy::inherent_to_string)]
+#![allow(clippy::enum_variant_names)]
+#![allow(clippy::module_inception)]
+
+pub mod ast;
+pub mod cache;
+pub mod collections;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod environment;
+pub mod error;
+pub mod expr;
+pub mod golden;
+pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod native;
+pub mod optimizer;
+pub mod parser;
+pub mod profiler;
+pub mod resolver;
+pub mod sandbox;
+pub mod scanner;
+pub mod snapshot;
+pub mod stmt;
+pub mod testing;
+pub mod trace;
+pub mod typecheck;
+pub mod watch;
+mod tests;
+
+pub use crate::environment::Environment;
+pub use crate::error::Error;
+pub use crate::expr::{Arity, Expr, LiteralValue};
+pub use crate::interpreter::Interpreter;
+pub use crate::native::{FromValue, IntoValue, NativeFunction};
+pub use crate::parser::Parser;
+pub use crate::resolver::Resolver;
+pub use crate::sandbox::{CancellationToken, Capabilities, Config, Limit};
+pub use crate::scanner::{Scanner, Token, TokenType};
+pub use crate::stmt::Stmt;
+pub use crate::typecheck::{Type, TypeChecker};
+
+/// A runtime value produced by a script.
+pub type Value = LiteralValue;
+
+/// Scans and parses `source` into a list of statements.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, Error> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().map_err(Error::Scan)?;
+
+    let mut parser = Parser::new(tokens);
+    parser.parse().map_err(Error::Parse)
+}
+
+/// Loads a program from the JSON written by `bolt ast --json` (see
+/// [`ast::to_json`]), ready to be run with [`Interpreter::execute`].
+pub fn load_ast(json: &str) -> Result<Vec<Stmt>, Error> {
+    let json = json::Json::parse(json).map_err(|e| Error::Parse(e.to_string()))?;
+    ast::from_json(&json).map_err(Error::Parse)
+}
+
+/// Parses `source` and runs the static type checker over it. Only annotated
+/// code and values whose type is obvious from the source are checked.
+pub fn check(source: &str) -> Result<(), Error> {
+    let stmts = parse(source)?;
+    TypeChecker::new()
+        .check_program(&stmts)
+        .map_err(|errors| Error::Type(errors.join("\n")))
+}
+
+/// Runs `contents` in `interpreter`, keeping any globals it defines. The
+/// program is passed through the [`optimizer`] first.
+pub fn run(interpreter: &mut Interpreter, contents: &str) -> Result<(), Error> {
+    let stmts = optimizer::optimize(parse(contents)?);
+    interpreter.execute(stmts.iter().collect())
+}
+
+/// Like [`run`], but executes the program exactly as parsed.
+pub fn run_unoptimized(interpreter: &mut Interpreter, contents: &str) -> Result<(), Error> {
+    let stmts = parse(contents)?;
+    interpreter.execute(stmts.iter().collect())
+}
+
+/// Runs `source` in a fresh interpreter and returns the value of its last
+/// statement if that is an expression statement, or `nil` otherwise.
+pub fn eval(source: &str) -> Result<Value, Error> {
+    let mut interpreter = Interpreter::new();
+    eval_with(&mut interpreter, source)
+}
+
+/// Like [`eval`], but runs in an existing interpreter.
+pub fn eval_with(interpreter: &mut Interpreter, source: &str) -> Result<Value, Error> {
+    let stmts = optimizer::optimize(parse(source)?);
+    let (last, init) = match stmts.split_last() {
+        Some((Stmt::Expression { expression }, init)) => (Some(expression), init),
+        _ => (None, &stmts[..]),
+    };
+
+    interpreter.execute(init.iter().collect())?;
+    match last {
+        Some(expression) => expression
+            .evaluate(interpreter)
+            .map_err(|msg| interpreter.runtime_error(msg)),
+        None => Ok(Value::Nil),
+    }
+}
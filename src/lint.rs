@@ -0,0 +1,133 @@
+use crate::error::Error;
+use crate::resolver::Resolver;
+use crate::stmt::Stmt;
+use crate::testing;
+
+const ALLOW: &str = "// bolt-allow(";
+
+/// Every rule `bolt lint` knows about. Each one can be silenced for a single
+/// line with a `// bolt-allow(rule)` comment on that line.
+pub const RULES: [&str; 6] = [
+    "unused-variable",
+    "unused-parameter",
+    "shadowing",
+    "unreachable-code",
+    "duplicate-declaration",
+    "top-level-return",
+];
+
+/// Something suspicious in a script that doesn't stop it from running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub rule: &'static str,
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {} [{}]", self.line, self.message, self.rule)
+    }
+}
+
+/// Resolves `source` and returns its warnings sorted by line, leaving out
+/// the ones suppressed by `bolt-allow` comments. Test blocks are checked
+/// along with the program.
+pub fn lint(source: &str) -> Result<Vec<Warning>, Error> {
+    let (mut program, tests) = testing::parse_tests(source)?;
+    program.extend(tests.into_iter().map(|test| Stmt::Block {
+        statements: test.body,
+    }));
+
+    let mut resolver = Resolver::new();
+    resolver.resolve_program(&program).map_err(Error::Parse)?;
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut warnings: Vec<Warning> = resolver
+        .warnings
+        .into_iter()
+        .filter(|warning| {
+            let line = lines.get(warning.line.wrapping_sub(1)).copied().unwrap_or("");
+            !allowed_rules(line).contains(&warning.rule)
+        })
+        .collect();
+    warnings.sort_by(|a, b| (a.line, a.rule, &a.message).cmp(&(b.line, b.rule, &b.message)));
+    Ok(warnings)
+}
+
+/// Rules named by `// bolt-allow(a, b)` comments on a source line.
+fn allowed_rules(line: &str) -> Vec<&str> {
+    let mut rules = vec![];
+    let mut rest = line;
+    while let Some(index) = rest.find(ALLOW) {
+        rest = &rest[index + ALLOW.len()..];
+        let Some(end) = rest.find(')') else { break };
+        rules.extend(rest[..end].split(',').map(|rule| rule.trim()));
+        rest = &rest[end..];
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(source: &str) -> Vec<(usize, &'static str)> {
+        lint(source)
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.line, warning.rule))
+            .collect()
+    }
+
+    #[test]
+    fn reports_unused_locals_and_parameters() {
+        let source = "fun f(a, b) {\n  var c = 1;\n  var _d = 2;\n  return a;\n}\nf(1, 2);\n";
+
+        assert_eq!(
+            rules(source),
+            vec![(1, "unused-parameter"), (2, "unused-variable")]
+        );
+    }
+
+    #[test]
+    fn assignment_is_not_a_use() {
+        let source = "{\n  var a = 1;\n  a = 2;\n}\n";
+
+        assert_eq!(rules(source), vec![(2, "unused-variable")]);
+    }
+
+    #[test]
+    fn reports_shadowing_and_duplicates() {
+        let source = "var a = 1;\nfun f() {\n  var a = 2;\n  var b = a;\n  var b = 3;\n  print b;\n}\nvar a = 4;\n";
+
+        assert_eq!(
+            rules(source),
+            vec![
+                (3, "shadowing"),
+                (4, "unused-variable"),
+                (5, "duplicate-declaration"),
+                (8, "duplicate-declaration"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_code_and_top_level_return() {
+        let source = "fun f() {\n  return 1;\n  print 2;\n  print 3;\n}\nreturn;\n";
+
+        assert_eq!(
+            rules(source),
+            vec![(3, "unreachable-code"), (6, "top-level-return")]
+        );
+    }
+
+    #[test]
+    fn bolt_allow_comment_suppresses_rule_on_its_line() {
+        let source = "fun f(a) { // bolt-allow(unused-parameter)\n  var b = 1; // bolt-allow(shadowing, unused-variable)\n  var c = 2; // bolt-allow(shadowing)\n}\n";
+
+        let warnings = lint(source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].to_string(), "Line 3: Variable 'c' is never used [unused-variable]");
+    }
+}
@@ -0,0 +1,547 @@
+use crate::json::Json;
+use crate::parser::Parser;
+use crate::resolver::{Resolver, Symbol, SymbolKind};
+use crate::scanner::{Scanner, Token};
+use crate::typecheck::TypeChecker;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Reads one `Content-Length` framed message. Returns `Ok(None)` at end of
+/// input.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Json>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        let read = reader.read_line(&mut header).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid Content-Length: {}", value.trim()))?,
+            );
+        }
+    }
+
+    let length = length.ok_or("Message without Content-Length header")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let body = String::from_utf8(body).map_err(|e| e.to_string())?;
+    Json::parse(&body).map(Some).map_err(|e| e.to_string())
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> Result<(), String> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body).map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())
+}
+
+const SEVERITY_ERROR: usize = 1;
+const SYMBOL_KIND_FUNCTION: usize = 12;
+const SYMBOL_KIND_VARIABLE: usize = 13;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+/// What the server knows about one open document.
+struct Analysis {
+    diagnostics: Vec<Json>,
+    symbols: Vec<Symbol>,
+    /// Every name token that refers to a symbol, including the declarations
+    /// themselves, paired with the symbol index.
+    occurrences: Vec<(Token, usize)>,
+}
+
+impl Analysis {
+    fn new(text: &str, previous: Option<Analysis>) -> Self {
+        let (diagnostics, resolved) = match check(text) {
+            Ok((resolver, type_errors)) => (
+                type_errors.iter().map(|msg| diagnostic(text, msg)).collect(),
+                Some(resolver),
+            ),
+            Err(errors) => (
+                errors.lines().map(|msg| diagnostic(text, msg)).collect(),
+                None,
+            ),
+        };
+
+        match resolved {
+            Some(resolver) => {
+                let mut occurrences: Vec<(Token, usize)> = resolver
+                    .symbols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, symbol)| (symbol.name.clone(), i))
+                    .collect();
+                occurrences.extend(
+                    resolver
+                        .references
+                        .iter()
+                        .filter_map(|r| r.symbol.map(|symbol| (r.name.clone(), symbol))),
+                );
+                Self {
+                    diagnostics,
+                    symbols: resolver.symbols,
+                    occurrences,
+                }
+            }
+            // Keep navigating with the last good analysis while the user is
+            // in the middle of an edit.
+            None => match previous {
+                Some(previous) => Self {
+                    diagnostics,
+                    ..previous
+                },
+                None => Self {
+                    diagnostics,
+                    symbols: vec![],
+                    occurrences: vec![],
+                },
+            },
+        }
+    }
+
+    fn symbol_at(&self, line: usize, character: usize) -> Option<usize> {
+        self.occurrences
+            .iter()
+            .find(|(token, _)| {
+                token.lineNumber == line + 1
+                    && token.column <= character
+                    && character <= token.column + token.lexeme.len()
+            })
+            .map(|(_, symbol)| *symbol)
+    }
+}
+
+/// Scans, parses and resolves `text`. Type errors don't stop navigation, so
+/// they are returned alongside the resolver.
+fn check(text: &str) -> Result<(Resolver, Vec<String>), String> {
+    let mut scanner = Scanner::new(text);
+    let tokens = scanner.scan_tokens()?;
+    let mut parser = Parser::new(tokens);
+    let stmts = parser.parse()?;
+
+    let mut resolver = Resolver::new();
+    resolver.resolve_program(&stmts)?;
+    let type_errors = TypeChecker::new().check_program(&stmts).err().unwrap_or_default();
+    Ok((resolver, type_errors))
+}
+
+/// Turns a `Line N: message` error into a diagnostic covering that line.
+fn diagnostic(text: &str, msg: &str) -> Json {
+    let (line, message) = match msg.strip_prefix("Line ").and_then(|rest| rest.split_once(": ")) {
+        Some((line, message)) => (line.parse::<usize>().unwrap_or(1), message),
+        None => (1, msg),
+    };
+    let line = line.saturating_sub(1);
+    let source_line = text.lines().nth(line).unwrap_or("");
+    let start = source_line.len() - source_line.trim_start().len();
+
+    Json::object(vec![
+        ("range", range(line, start, source_line.trim_end().len())),
+        ("severity", SEVERITY_ERROR.into()),
+        ("source", "bolt".into()),
+        ("message", message.into()),
+    ])
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+fn range(line: usize, start: usize, end: usize) -> Json {
+    Json::object(vec![
+        ("start", position(line, start)),
+        ("end", position(line, end)),
+    ])
+}
+
+fn token_range(token: &Token) -> Json {
+    let line = token.lineNumber.saturating_sub(1);
+    range(line, token.column, token.column + token.lexeme.len())
+}
+
+fn location(uri: &str, token: &Token) -> Json {
+    Json::object(vec![("uri", uri.into()), ("range", token_range(token))])
+}
+
+fn signature(symbol: &Symbol) -> String {
+    match symbol.kind {
+        SymbolKind::Function => {
+            let mut params: Vec<String> = symbol.params.iter().map(|p| p.lexeme.clone()).collect();
+            if let (None, Some(last)) = (symbol.arity.max, params.last_mut()) {
+                last.insert_str(0, "...");
+            }
+            format!("fun {}({})", symbol.name.lexeme, params.join(", "))
+        }
+        SymbolKind::Variable => format!("var {}", symbol.name.lexeme),
+        SymbolKind::Parameter => format!("(parameter) {}", symbol.name.lexeme),
+    }
+}
+
+/// A language server for Bolt. Messages are handled one at a time by
+/// `handle`, which returns the responses and notifications to send back.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Analysis>,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Serves requests from `reader` until the client sends `exit` or closes
+    /// the stream.
+    pub fn run(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> Result<(), String> {
+        while let Some(message) = read_message(reader)? {
+            for reply in self.handle(&message) {
+                write_message(writer, &reply)?;
+            }
+            if self.exited {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Some(Self::initialize()),
+            "shutdown" => Some(Json::Null),
+            "exit" => {
+                self.exited = true;
+                None
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|d| d.get("uri")).and_then(|u| u.as_str());
+                let text = document.and_then(|d| d.get("text")).and_then(|t| t.as_str());
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    return vec![self.update(uri, text)];
+                }
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = Self::uri(&params);
+                let text = params
+                    .get("contentChanges")
+                    .and_then(|c| c.as_array())
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(|t| t.as_str());
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    return vec![self.update(&uri, text)];
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = Self::uri(&params) {
+                    self.documents.remove(&uri);
+                    return vec![Self::publish_diagnostics(&uri, vec![])];
+                }
+                None
+            }
+            "textDocument/definition" => Some(self.definition(&params)),
+            "textDocument/references" => Some(self.references(&params)),
+            "textDocument/hover" => Some(self.hover(&params)),
+            "textDocument/documentSymbol" => Some(self.document_symbols(&params)),
+            _ => {
+                // Unknown notifications are ignored; unknown requests get an error.
+                return match id {
+                    Some(id) => vec![Json::object(vec![
+                        ("jsonrpc", "2.0".into()),
+                        ("id", id),
+                        (
+                            "error",
+                            Json::object(vec![
+                                ("code", METHOD_NOT_FOUND.into()),
+                                ("message", format!("Method not found: {}", method).into()),
+                            ]),
+                        ),
+                    ])],
+                    None => vec![],
+                };
+            }
+        };
+
+        match (id, result) {
+            (Some(id), Some(result)) => vec![Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ])],
+            _ => vec![],
+        }
+    }
+
+    fn initialize() -> Json {
+        Json::object(vec![
+            (
+                "capabilities",
+                Json::object(vec![
+                    ("textDocumentSync", 1usize.into()),
+                    ("definitionProvider", true.into()),
+                    ("referencesProvider", true.into()),
+                    ("hoverProvider", true.into()),
+                    ("documentSymbolProvider", true.into()),
+                ]),
+            ),
+            ("serverInfo", Json::object(vec![("name", "bolt".into())])),
+        ])
+    }
+
+    fn uri(params: &Json) -> Option<String> {
+        params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(|u| u.as_str())
+            .map(|u| u.to_string())
+    }
+
+    fn update(&mut self, uri: &str, text: &str) -> Json {
+        let previous = self.documents.remove(uri);
+        let analysis = Analysis::new(text, previous);
+        let diagnostics = analysis.diagnostics.clone();
+        self.documents.insert(uri.to_string(), analysis);
+        Self::publish_diagnostics(uri, diagnostics)
+    }
+
+    fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+        Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object(vec![("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+            ),
+        ])
+    }
+
+    /// The document and symbol under the cursor of a text document
+    /// position request.
+    fn lookup(&self, params: &Json) -> Option<(String, &Analysis, usize)> {
+        let uri = Self::uri(params)?;
+        let analysis = self.documents.get(&uri)?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let symbol = analysis.symbol_at(line, character)?;
+        Some((uri, analysis, symbol))
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        match self.lookup(params) {
+            Some((uri, analysis, symbol)) => location(&uri, &analysis.symbols[symbol].name),
+            None => Json::Null,
+        }
+    }
+
+    fn references(&self, params: &Json) -> Json {
+        let Some((uri, analysis, symbol)) = self.lookup(params) else {
+            return Json::Array(vec![]);
+        };
+        let include_declaration = params
+            .get("context")
+            .and_then(|c| c.get("includeDeclaration"))
+            .and_then(|i| i.as_bool())
+            .unwrap_or(true);
+        let declaration = &analysis.symbols[symbol].name;
+
+        let locations = analysis
+            .occurrences
+            .iter()
+            .filter(|(token, s)| {
+                *s == symbol
+                    && (include_declaration
+                        || (token.lineNumber, token.column)
+                            != (declaration.lineNumber, declaration.column))
+            })
+            .map(|(token, _)| location(&uri, token))
+            .collect();
+        Json::Array(locations)
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let Some((_, analysis, symbol)) = self.lookup(params) else {
+            return Json::Null;
+        };
+        let symbol = &analysis.symbols[symbol];
+
+        let mut contents = format!("```bolt\n{}\n```", signature(symbol));
+        if symbol.kind == SymbolKind::Function {
+            contents.push_str(&format!("\nArity: {}", symbol.arity.to_string()));
+        }
+        if let Some(container) = symbol.container {
+            contents.push_str(&format!(
+                "\nDeclared in `{}`",
+                analysis.symbols[container].name.lexeme
+            ));
+        }
+
+        Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![("kind", "markdown".into()), ("value", contents.into())]),
+            ),
+            ("range", token_range(&symbol.name)),
+        ])
+    }
+
+    fn document_symbols(&self, params: &Json) -> Json {
+        let Some(analysis) = Self::uri(params).and_then(|uri| self.documents.get(&uri)) else {
+            return Json::Array(vec![]);
+        };
+        Json::Array(Self::symbol_tree(analysis, None))
+    }
+
+    fn symbol_tree(analysis: &Analysis, container: Option<usize>) -> Vec<Json> {
+        analysis
+            .symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| symbol.container == container && symbol.kind != SymbolKind::Parameter)
+            .map(|(i, symbol)| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function => SYMBOL_KIND_FUNCTION,
+                    _ => SYMBOL_KIND_VARIABLE,
+                };
+                let mut members = vec![
+                    ("name", symbol.name.lexeme.clone().into()),
+                    ("detail", signature(symbol).into()),
+                    ("kind", kind.into()),
+                    ("range", token_range(&symbol.name)),
+                    ("selectionRange", token_range(&symbol.name)),
+                ];
+                if symbol.kind == SymbolKind::Function {
+                    members.push(("children", Self::symbol_tree(analysis, Some(i)).into()));
+                }
+                Json::object(members)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.bolt";
+
+    fn open(server: &mut Server, text: &str) -> Json {
+        let message = Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/didOpen".into()),
+            (
+                "params",
+                Json::object(vec![(
+                    "textDocument",
+                    Json::object(vec![("uri", URI.into()), ("text", text.into())]),
+                )]),
+            ),
+        ]);
+        server.handle(&message).remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Json {
+        let message = Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", 1usize.into()),
+            ("method", method.into()),
+            (
+                "params",
+                Json::object(vec![
+                    ("textDocument", Json::object(vec![("uri", URI.into())])),
+                    ("position", position(line, character)),
+                ]),
+            ),
+        ]);
+        server.handle(&message).remove(0).get("result").unwrap().clone()
+    }
+
+    const SOURCE: &str = "fun add(a, b) {\n    return a + b;\n}\nvar total = add(1, 2);\nprint total;\n";
+
+    #[test]
+    fn publishes_parse_errors_as_diagnostics() {
+        let mut server = Server::new();
+        let notification = open(&mut server, "var a = 1;\nprint a\n");
+
+        let diagnostics = notification.get("params").unwrap().get("diagnostics").unwrap();
+        let diagnostic = &diagnostics.as_array().unwrap()[0];
+        assert_eq!(
+            diagnostic.get("message").and_then(|m| m.as_str()),
+            Some("Expected ';' after value.")
+        );
+        let line = diagnostic.get("range").unwrap().get("start").unwrap().get("line");
+        assert_eq!(line.and_then(|l| l.as_usize()), Some(2));
+    }
+
+    #[test]
+    fn publishes_resolver_errors_on_their_line() {
+        let mut server = Server::new();
+        let notification = open(&mut server, "var a = 1;\n{\n  var b = b;\n}\n");
+
+        let diagnostics = notification.get("params").unwrap().get("diagnostics").unwrap();
+        let diagnostic = &diagnostics.as_array().unwrap()[0];
+        assert_eq!(
+            diagnostic.get("message").and_then(|m| m.as_str()),
+            Some("Can't read local variable on its own initializer")
+        );
+        let line = diagnostic.get("range").unwrap().get("start").unwrap().get("line");
+        assert_eq!(line.and_then(|l| l.as_usize()), Some(2));
+    }
+
+    #[test]
+    fn finds_definition_and_references() {
+        let mut server = Server::new();
+        open(&mut server, SOURCE);
+
+        // `b` in `a + b` on line 1 goes to the parameter on line 0.
+        let definition = request(&mut server, "textDocument/definition", 1, 15);
+        assert_eq!(definition.get("range"), Some(&range(0, 11, 12)));
+
+        let references = request(&mut server, "textDocument/references", 4, 7);
+        assert_eq!(
+            references,
+            Json::Array(vec![
+                Json::object(vec![("uri", URI.into()), ("range", range(3, 4, 9))]),
+                Json::object(vec![("uri", URI.into()), ("range", range(4, 6, 11))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn hover_shows_function_arity() {
+        let mut server = Server::new();
+        open(&mut server, SOURCE);
+
+        let hover = request(&mut server, "textDocument/hover", 3, 13);
+        let value = hover.get("contents").unwrap().get("value").unwrap();
+        assert_eq!(value.as_str(), Some("```bolt\nfun add(a, b)\n```\nArity: 2"));
+    }
+
+    #[test]
+    fn round_trips_framed_messages() {
+        let mut buffer = vec![];
+        let message = Json::object(vec![("jsonrpc", "2.0".into()), ("method", "exit".into())]);
+        write_message(&mut buffer, &message).unwrap();
+
+        let mut reader = std::io::Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
@@ -1,47 +1,182 @@
-#![allow(dead_code)]
-#![allow(non_snake_case)]
-#![allow(unused_variables)]
-#![allow(unused_imports)]
-
-mod scanner;
-mod expr;
-mod parser;
-mod interpreter;
-mod stmt;
-mod resolver;
-mod tests;
-mod environment;
-use crate::scanner::*;
-use crate::parser::*;
-use crate::interpreter::*;
-
-use core::num;
+use bolt::coverage::Coverage;
+use bolt::debugger::{DebugHook, Debugger, Hooks};
+use bolt::profiler::Profiler;
+use bolt::trace::Tracer;
+use bolt::{
+    ast, cache, check, dap, golden, lint, load_ast, lsp, optimizer, parse, run, run_unoptimized,
+    watch, Capabilities, Config, Interpreter,
+};
+
 use std::env;
 use std::fs;
+use std::cell::RefCell;
 use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::result;
+use std::rc::Rc;
+
+/// Command-line flags that change how a script file is run.
+struct Options {
+    optimize: bool,
+    profile: bool,
+    coverage: bool,
+    /// `--no-cache` always parses the script instead of reading `name.boltc`.
+    cache: bool,
+    /// `--watch` re-runs the script's definitions whenever it changes.
+    watch: bool,
+    /// `--trace` traces everything; `--trace=a,b` only calls to `a` and `b`.
+    trace: Option<Vec<String>>,
+}
+
+impl Options {
+    const FLAGS: [&'static str; 6] = [
+        "--no-opt",
+        "--profile",
+        "--coverage",
+        "--no-cache",
+        "--watch",
+        "--trace",
+    ];
+
+    fn from_args(args: &[String]) -> Self {
+        let has = |flag: &str| args.iter().any(|arg| arg == flag);
+        let trace = args.iter().find_map(|arg| match arg.strip_prefix("--trace") {
+            Some("") => Some(vec![]),
+            Some(names) => names
+                .strip_prefix('=')
+                .map(|names| names.split(',').map(|name| name.to_string()).collect()),
+            None => None,
+        });
+        Self {
+            optimize: !has("--no-opt"),
+            profile: has("--profile"),
+            coverage: has("--coverage"),
+            cache: !has("--no-cache"),
+            watch: has("--watch"),
+            trace,
+        }
+    }
+
+    fn is_flag(arg: &str) -> bool {
+        Self::FLAGS.contains(&arg) || arg.starts_with("--trace=")
+    }
+
+    /// A tracer writing to stderr, if `--trace` was given.
+    fn tracer(&self) -> Option<Rc<RefCell<Tracer>>> {
+        let functions = self.trace.as_ref()?;
+        let tracer = Tracer::new(Rc::new(RefCell::new(io::stderr())), functions);
+        Some(Rc::new(RefCell::new(tracer)))
+    }
+}
 
-fn run_file(path: &str) -> Result<(), String> {
-    let mut interpreter = Interpreter::new(); 
+/// The CLI runs the user's own scripts, so they get every native, including
+/// file I/O, which embedders only get by asking for it.
+fn trusted_interpreter() -> Interpreter {
+    let config = Config {
+        capabilities: Capabilities::all(),
+        ..Config::default()
+    };
+    Interpreter::with_config(
+        Rc::new(RefCell::new(io::stdout())),
+        Rc::new(RefCell::new(io::stderr())),
+        config,
+    )
+}
+
+fn run_file(path: &str, options: &Options) -> Result<(), String> {
+    let mut interpreter = trusted_interpreter();
     match fs::read_to_string(path) {
-        Err(msg) => return Err(msg.to_string()),
-        Ok(contents) => return run(&mut interpreter, &contents),
+        Err(msg) => Err(msg.to_string()),
+        Ok(contents) => {
+            let program = if options.cache {
+                cache::load_or_compile(Path::new(path), &contents)
+            } else {
+                check(&contents).and_then(|_| parse(&contents))
+            }
+            .map_err(|e| e.to_string())?;
+            let profiler = options
+                .profile
+                .then(|| Rc::new(RefCell::new(Profiler::new())));
+            let coverage = match options.coverage {
+                true => Some(Rc::new(RefCell::new(Coverage::new(&program)))),
+                false => None,
+            };
+            let mut hooks: Vec<Rc<RefCell<dyn DebugHook>>> = vec![];
+            if let Some(tracer) = options.tracer() {
+                hooks.push(tracer);
+            }
+            if let Some(profiler) = &profiler {
+                hooks.push(profiler.clone());
+            }
+            if let Some(coverage) = &coverage {
+                hooks.push(coverage.clone());
+            }
+            if !hooks.is_empty() {
+                interpreter.set_debug_hook(Rc::new(RefCell::new(Hooks(hooks))));
+            }
+
+            // Coverage is reported against the source as written, so the
+            // optimizer must not remove or merge statements.
+            let program = if options.optimize && !options.coverage {
+                optimizer::optimize(program)
+            } else {
+                program
+            };
+            let result = interpreter.execute(program.iter().collect());
+            if let Some(profiler) = profiler {
+                write_profile(path, &mut profiler.borrow_mut())?;
+            }
+            if let Some(coverage) = coverage {
+                write_coverage(path, &coverage.borrow())?;
+            }
+            result.map_err(|e| e.to_string())
+        }
     }
 }
 
-pub fn run(interpreter: &mut Interpreter, contents: &str) -> Result<(), String> {
-    let mut scanner = Scanner::new(contents);
-    let tokens = scanner.scan_tokens()?;
+/// Runs the script, then keeps the interpreter alive and re-runs the
+/// script's definitions every time the file changes.
+fn watch_file(path: &str, options: &Options) -> Result<(), String> {
+    let mut interpreter = trusted_interpreter();
+    if let Some(tracer) = options.tracer() {
+        interpreter.set_debug_hook(tracer);
+    }
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let result = check(&contents).and_then(|_| match options.optimize {
+        true => run(&mut interpreter, &contents),
+        false => run_unoptimized(&mut interpreter, &contents),
+    });
+    if let Err(err) = result {
+        interpreter.report_error(&err.to_string())?;
+    }
+
+    eprintln!("Watching {} for changes", path);
+    watch::watch(Path::new(path), |contents| {
+        match watch::reload(&mut interpreter, contents) {
+            Ok(reload) => eprintln!("Reloaded {}: {}", path, reload.summary()),
+            Err(err) => interpreter.report_error(&err.to_string())?,
+        }
+        Ok(())
+    })
+}
 
-    let mut parser = Parser::new(tokens);
-    let stmts = parser.parse()?;
-    interpreter.interpret(stmts.iter().map(|b| b).collect())?;
-    return Ok(());
+/// Prints the profile report to stderr and writes the folded stacks next to
+/// the script, as `name.folded`.
+fn write_profile(path: &str, profiler: &mut Profiler) -> Result<(), String> {
+    profiler.finish();
+    let folded_path = Path::new(path).with_extension("folded");
+    fs::write(&folded_path, profiler.folded())
+        .map_err(|e| format!("{}: {}", folded_path.display(), e))?;
+    eprint!("{}", profiler.report());
+    eprintln!("Folded stacks written to {}", folded_path.display());
+    Ok(())
 }
 
-fn run_prompt() -> Result<(), String> {
-    let mut interpreter = Interpreter::new();
+fn run_prompt(options: &Options) -> Result<(), String> {
+    let mut interpreter = trusted_interpreter();
+    if let Some(tracer) = options.tracer() {
+        interpreter.set_debug_hook(tracer);
+    }
     loop {
         print!("> ");
         match io::stdout().flush() {
@@ -53,30 +188,206 @@ fn run_prompt() -> Result<(), String> {
         let mut handle = stdin.lock();
         match handle.read_line(&mut buffer) {
             Ok(n) => {
-                dbg!(n);
                 if n <= 2 {
                     return Ok(());
                 }
             }
             Err(_) => return Err("Couldn't read line".to_string()),
         }
-        println!("ECHO: {}", buffer);
         match run(&mut interpreter, &buffer) {
             Ok(_) => (),
-            Err(msg) => println!("{}", msg),
+            Err(err) => interpreter.report_error(&err.to_string())?,
+        }
+    }
+}
+
+fn run_tests(paths: &[String]) -> Result<bool, String> {
+    let paths: Vec<PathBuf> = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths.iter().map(PathBuf::from).collect()
+    };
+    let cases = golden::discover(&paths)?;
+
+    let results = golden::run_cases(&cases);
+    let mut passed = 0;
+    let mut failed = 0;
+    for result in &results {
+        let status = if result.passed() { "PASS" } else { "FAIL" };
+        if result.tests.is_empty() {
+            println!("{} {} ({:?})", status, result.path.display(), result.duration);
+            for failure in &result.failures {
+                for line in failure.lines() {
+                    println!("    {}", line);
+                }
+            }
+            if result.passed() {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+            continue;
+        }
+
+        println!("{} {}", status, result.path.display());
+        for test in &result.tests {
+            match &test.error {
+                None => {
+                    passed += 1;
+                    println!("    ok   {} (line {}, {:?})", test.name, test.line, test.duration);
+                }
+                Some(msg) => {
+                    failed += 1;
+                    println!("    FAIL {} (line {}, {:?})", test.name, test.line, test.duration);
+                    match test.failed_line {
+                        Some(line) => println!("         line {}: {}", line, msg),
+                        None => println!("         {}", msg),
+                    }
+                    for line in test.output.lines() {
+                        println!("         | {}", line);
+                    }
+                }
+            }
+        }
+    }
+    println!("\n{} passed, {} failed", passed, failed);
+    Ok(failed == 0)
+}
+
+/// Runs `path` under the terminal debugger. The program is not optimized, so
+/// every statement in the source can be stopped at.
+fn debug_file(path: &str) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let input = Box::new(io::BufReader::new(io::stdin()));
+    let debugger = Debugger::new(&source, input, Rc::new(RefCell::new(io::stdout())));
+
+    let mut interpreter = trusted_interpreter();
+    interpreter.set_debug_hook(Rc::new(RefCell::new(debugger)));
+    run_unoptimized(&mut interpreter, &source).map_err(|e| e.to_string())
+}
+
+/// Prints the coverage summary to stderr and writes an LCOV tracefile next
+/// to the script, as `name.lcov`.
+fn write_coverage(path: &str, coverage: &Coverage) -> Result<(), String> {
+    let lcov_path = Path::new(path).with_extension("lcov");
+    fs::write(&lcov_path, coverage.lcov(path))
+        .map_err(|e| format!("{}: {}", lcov_path.display(), e))?;
+    eprint!("{}", coverage.summary());
+    eprintln!("LCOV written to {}", lcov_path.display());
+    Ok(())
+}
+
+/// `bolt ast --json file.bolt` prints the syntax tree as JSON, and
+/// `bolt ast --run file.json` executes a tree in that format.
+fn ast_command(mode: &str, path: &str) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    match mode {
+        "--json" => {
+            let stmts = parse(&contents).map_err(|e| e.to_string())?;
+            println!("{}", ast::to_json(&stmts).stringify(2));
+            Ok(())
+        }
+        "--run" => {
+            let stmts = load_ast(&contents).map_err(|e| format!("{}: {}", path, e))?;
+            let mut interpreter = trusted_interpreter();
+            interpreter
+                .execute(stmts.iter().collect())
+                .map_err(|e| e.to_string())
+        }
+        other => Err(format!("Unknown option '{}', expected --json or --run", other)),
+    }
+}
+
+fn lint_files(paths: &[String]) -> Result<bool, String> {
+    let mut clean = true;
+    for path in paths {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let warnings = lint::lint(&source).map_err(|e| format!("{}: {}", path, e))?;
+        for warning in &warnings {
+            println!("{}: {}", path, warning);
         }
+        clean &= warnings.is_empty();
     }
+    Ok(clean)
 }
 
 fn main() {
     // env::set_var("RUST_BACKTRACE", "1");
 
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let options = Options::from_args(&args);
+    args.retain(|arg| !Options::is_flag(arg));
 
-    if args.len() > 2 {
+    if args.len() == 2 && args[1] == "lsp" {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        let mut writer = io::stdout();
+        match lsp::Server::new().run(&mut reader, &mut writer) {
+            Ok(_) => exit(0),
+            Err(msg) => {
+                eprintln!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() == 2 && args[1] == "dap" {
+        let reader = Box::new(io::BufReader::new(io::stdin()));
+        match dap::run(reader, Rc::new(RefCell::new(io::stdout()))) {
+            Ok(_) => exit(0),
+            Err(msg) => {
+                eprintln!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() >= 2 && args[1] == "test" {
+        match run_tests(&args[2..]) {
+            Ok(true) => exit(0),
+            Ok(false) => exit(1),
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() == 3 && args[1] == "debug" {
+        match debug_file(&args[2]) {
+            Ok(_) => exit(0),
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() == 4 && args[1] == "ast" {
+        match ast_command(&args[2], &args[3]) {
+            Ok(_) => exit(0),
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() == 3 && args[1] == "run" {
+        let result = match options.watch {
+            true => watch_file(&args[2], &options),
+            false => run_file(&args[2], &options),
+        };
+        match result {
+            Ok(_) => exit(0),
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() > 2 && args[1] == "lint" {
+        match lint_files(&args[2..]) {
+            Ok(true) => exit(0),
+            Ok(false) => exit(1),
+            Err(msg) => {
+                println!("ERROR: {}", msg);
+                exit(1);
+            }
+        }
+    } else if args.len() > 2 {
         // println!("Usage: bolt[script]");
     } else if args.len() == 2 {
-        match run_file(&args[1]) {
+        match run_file(&args[1], &options) {
             Ok(_) => exit(0),
             Err(msg) => {
                 println!("ERROR: {}", msg);
@@ -84,7 +395,7 @@ fn main() {
             }
         }
     } else {
-        match run_prompt() {
+        match run_prompt(&options) {
             Ok(_) => exit(0),
             Err(msg) => {
                 println!("ERROR:{}", msg);
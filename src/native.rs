@@ -0,0 +1,242 @@
+use crate::expr::LiteralValue;
+use std::rc::Rc;
+
+/// Conversion from a Bolt value into a Rust type, used to unpack the
+/// arguments of native functions.
+pub trait FromValue: Sized {
+    /// Name of the Bolt type this conversion accepts, used in error messages.
+    fn type_name() -> &'static str;
+
+    fn from_value(value: &LiteralValue) -> Option<Self>;
+}
+
+/// Conversion from a Rust type into a Bolt value, used to hand the result of
+/// native functions back to the interpreter.
+pub trait IntoValue {
+    fn into_value(self) -> LiteralValue;
+}
+
+impl FromValue for LiteralValue {
+    fn type_name() -> &'static str {
+        "Any"
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn type_name() -> &'static str {
+        "Number"
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> &'static str {
+        "String"
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::StringValue(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> &'static str {
+        "Boolean"
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::True => Some(true),
+            LiteralValue::False => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<LiteralValue> {
+    fn type_name() -> &'static str {
+        "Array"
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::Array(items) => Some(items.as_ref().clone()),
+            LiteralValue::Range { .. } => {
+                Some(value.range_numbers().map(LiteralValue::Number).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> &'static str {
+        T::type_name()
+    }
+
+    fn from_value(value: &LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::Nil => Some(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl IntoValue for LiteralValue {
+    fn into_value(self) -> LiteralValue {
+        self
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::Number(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::StringValue(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::StringValue(self.to_string())
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::from_bool(self)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::Nil
+    }
+}
+
+impl IntoValue for Vec<LiteralValue> {
+    fn into_value(self) -> LiteralValue {
+        LiteralValue::Array(Rc::new(self))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> LiteralValue {
+        match self {
+            Some(value) => value.into_value(),
+            None => LiteralValue::Nil,
+        }
+    }
+}
+
+/// A Rust closure that can be exposed to Bolt scripts. Implemented for every
+/// `Fn(A, B, ..) -> Result<R, String>` whose arguments implement `FromValue`
+/// and whose result implements `IntoValue`.
+pub trait NativeFunction<Args> {
+    fn arity(&self) -> usize;
+
+    fn invoke(&self, name: &str, args: &[LiteralValue]) -> Result<LiteralValue, String>;
+}
+
+fn argument<T: FromValue>(name: &str, args: &[LiteralValue], index: usize) -> Result<T, String> {
+    let value = &args[index];
+    T::from_value(value).ok_or_else(|| {
+        format!(
+            "{} expected argument {} to be {} but got {}",
+            name,
+            index + 1,
+            T::type_name(),
+            value.to_type()
+        )
+    })
+}
+
+macro_rules! impl_native_function {
+    ($arity:expr; $($arg:ident: $ty:ident => $index:expr),*) => {
+        impl<Fun, Ret, $($ty),*> NativeFunction<($($ty,)*)> for Fun
+        where
+            Fun: Fn($($ty),*) -> Result<Ret, String>,
+            Ret: IntoValue,
+            $($ty: FromValue,)*
+        {
+            fn arity(&self) -> usize {
+                $arity
+            }
+
+            fn invoke(&self, name: &str, args: &[LiteralValue]) -> Result<LiteralValue, String> {
+                if args.len() != $arity {
+                    return Err(format!(
+                        "Callable {} expected {} arguments but got {}",
+                        name,
+                        $arity,
+                        args.len()
+                    ));
+                }
+                $(let $arg = argument::<$ty>(name, args, $index)?;)*
+                (self)($($arg),*).map(IntoValue::into_value)
+            }
+        }
+    };
+}
+
+impl_native_function!(0;);
+impl_native_function!(1; a: A => 0);
+impl_native_function!(2; a: A => 0, b: B => 1);
+impl_native_function!(3; a: A => 0, b: B => 1, c: C => 2);
+impl_native_function!(4; a: A => 0, b: B => 1, c: C => 2, d: D => 3);
+impl_native_function!(5; a: A => 0, b: B => 1, c: C => 2, d: D => 3, e: E => 4);
+impl_native_function!(6; a: A => 0, b: B => 1, c: C => 2, d: D => 3, e: E => 4, f: F => 5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_arguments_and_result() {
+        let add = |a: f64, b: f64| -> Result<f64, String> { Ok(a + b) };
+        assert_eq!(NativeFunction::arity(&add), 2);
+
+        let args = vec![LiteralValue::Number(1.0), LiteralValue::Number(2.0)];
+        let result = add.invoke("add", &args).unwrap();
+        assert_eq!(result, LiteralValue::Number(3.0));
+    }
+
+    #[test]
+    fn reports_argument_type_mismatch() {
+        let repeat = |s: String, n: f64| -> Result<String, String> { Ok(s.repeat(n as usize)) };
+
+        let args = vec![
+            LiteralValue::StringValue("ab".to_string()),
+            LiteralValue::True,
+        ];
+        let err = repeat.invoke("repeat", &args).unwrap_err();
+        assert_eq!(err, "repeat expected argument 2 to be Number but got Boolean");
+    }
+
+    #[test]
+    fn optional_argument_accepts_nil() {
+        let describe = |s: Option<String>| -> Result<String, String> {
+            Ok(s.unwrap_or_else(|| "nothing".to_string()))
+        };
+
+        let result = describe.invoke("describe", &[LiteralValue::Nil]).unwrap();
+        assert_eq!(result, LiteralValue::StringValue("nothing".to_string()));
+    }
+}
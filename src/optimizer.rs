@@ -0,0 +1,330 @@
+use crate::expr::{apply_binary, apply_unary, Expr, LiteralValue};
+use crate::scanner::TokenType;
+use crate::stmt::Stmt;
+
+/// Rewrites a parsed program into an equivalent one that does less work at
+/// runtime: constant expressions are folded, branches and loops whose
+/// condition is a constant are removed or inlined, and blocks that declare
+/// nothing (like the ones the parser builds for `for` loops) are merged into
+/// their parent.
+///
+/// Folding only happens when it succeeds, so expressions like `1 - "a"` are
+/// left alone and still fail at runtime with the usual error.
+pub fn optimize(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    optimize_list(stmts.into_iter().map(Box::new).collect(), true)
+        .into_iter()
+        .map(|stmt| *stmt)
+        .collect()
+}
+
+/// Optimizes a statement list. Blocks are only merged into lists where that
+/// can't change when a `return` is noticed, which rules out function bodies:
+/// functions check for a return after each of their top-level statements.
+fn optimize_list(stmts: Vec<Box<Stmt>>, flatten: bool) -> Vec<Box<Stmt>> {
+    let mut result = vec![];
+    for stmt in stmts {
+        match optimize_stmt(*stmt) {
+            Some(Stmt::Block { statements }) if flatten && !declares(&statements) => {
+                result.extend(statements)
+            }
+            Some(stmt) => result.push(Box::new(stmt)),
+            None => (),
+        }
+    }
+    result
+}
+
+fn declares(stmts: &[Box<Stmt>]) -> bool {
+    stmts
+        .iter()
+        .any(|stmt| matches!(stmt.as_ref(), Stmt::Var { .. } | Stmt::Function { .. }))
+}
+
+fn empty_block() -> Box<Stmt> {
+    Box::new(Stmt::Block { statements: vec![] })
+}
+
+fn is_true(value: &LiteralValue) -> bool {
+    value.is_truthy() == LiteralValue::True
+}
+
+/// Returns the optimized statement, or `None` if it does nothing at all.
+fn optimize_stmt(stmt: Stmt) -> Option<Stmt> {
+    match stmt {
+        Stmt::Expression { expression } => Some(Stmt::Expression {
+            expression: fold(expression),
+        }),
+        Stmt::Print {
+            keyword,
+            expression,
+        } => Some(Stmt::Print {
+            keyword,
+            expression: fold(expression),
+        }),
+        Stmt::Var {
+            name,
+            type_annotation,
+            initializer,
+        } => Some(Stmt::Var {
+            name,
+            type_annotation,
+            initializer: fold(initializer),
+        }),
+        Stmt::Block { statements } => {
+            let mut statements = optimize_list(statements, true);
+            match statements.len() {
+                0 => None,
+                1 if !declares(&statements) => statements.pop().map(|stmt| *stmt),
+                _ => Some(Stmt::Block { statements }),
+            }
+        }
+        Stmt::IfStmt {
+            keyword,
+            predicate,
+            then,
+            els,
+        } => match fold(predicate) {
+            Expr::Literal { value } if is_true(&value) => optimize_stmt(*then),
+            Expr::Literal { .. } => els.and_then(|els| optimize_stmt(*els)),
+            predicate => Some(Stmt::IfStmt {
+                keyword,
+                predicate,
+                then: optimize_stmt(*then).map(Box::new).unwrap_or_else(empty_block),
+                els: els.and_then(|els| optimize_stmt(*els)).map(Box::new),
+            }),
+        },
+        Stmt::WhileStmt {
+            keyword,
+            condition,
+            body,
+        } => match fold(condition) {
+            Expr::Literal { value } if !is_true(&value) => None,
+            condition => Some(Stmt::WhileStmt {
+                keyword,
+                condition,
+                body: optimize_stmt(*body).map(Box::new).unwrap_or_else(empty_block),
+            }),
+        },
+        Stmt::ForIn {
+            keyword,
+            variable,
+            iterable,
+            body,
+        } => Some(Stmt::ForIn {
+            keyword,
+            variable,
+            iterable: fold(iterable),
+            body: optimize_stmt(*body).map(Box::new).unwrap_or_else(empty_block),
+        }),
+        Stmt::Function {
+            name,
+            params,
+            param_types,
+            defaults,
+            rest,
+            return_type,
+            body,
+        } => Some(Stmt::Function {
+            name,
+            params,
+            param_types,
+            defaults: fold_defaults(defaults),
+            rest,
+            return_type,
+            body: optimize_list(body, false),
+        }),
+        Stmt::ReturnStmt { keyword, value } => Some(Stmt::ReturnStmt {
+            keyword,
+            value: value.map(fold),
+        }),
+    }
+}
+
+/// Folds constant subexpressions of `expr`.
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let (left, right) = (fold(*left), fold(*right));
+            if let (Expr::Literal { value: l }, Expr::Literal { value: r }) = (&left, &right) {
+                // Ranges aren't folded: literals in a program are scalars.
+                match apply_binary(l, operator.token_type, r) {
+                    Ok(LiteralValue::Range { .. }) | Err(_) => {}
+                    Ok(value) => return Expr::Literal { value },
+                }
+            }
+            Expr::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Expr::Unary { operator, right } => {
+            let right = fold(*right);
+            if let Expr::Literal { value } = &right {
+                if let Ok(value) = apply_unary(operator.token_type, value) {
+                    return Expr::Literal { value };
+                }
+            }
+            Expr::Unary {
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            let (left, right) = (fold(*left), fold(*right));
+            // Same short-circuit results as `Expr::evaluate`: `or` yields
+            // its left operand when that is truthy, `and` yields `false`
+            // when its left operand is falsy.
+            match (&left, operator.token_type) {
+                (Expr::Literal { value }, TokenType::Or) if is_true(value) => left,
+                (Expr::Literal { value }, TokenType::And) if !is_true(value) => Expr::Literal {
+                    value: LiteralValue::False,
+                },
+                (Expr::Literal { .. }, TokenType::Or | TokenType::And) => right,
+                _ => Expr::Logical {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                },
+            }
+        }
+        Expr::Grouping { expression } => match fold(*expression) {
+            literal @ Expr::Literal { .. } => literal,
+            expression => Expr::Grouping {
+                expression: Box::new(expression),
+            },
+        },
+        Expr::Assign { name, value } => Expr::Assign {
+            name,
+            value: Box::new(fold(*value)),
+        },
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+            named,
+        } => Expr::Call {
+            callee: Box::new(fold(*callee)),
+            paren,
+            arguments: arguments.into_iter().map(fold).collect(),
+            named: named
+                .into_iter()
+                .map(|(name, value)| (name, fold(value)))
+                .collect(),
+        },
+        Expr::AnonFunction {
+            paren,
+            arguments,
+            param_types,
+            defaults,
+            rest,
+            return_type,
+            body,
+        } => Expr::AnonFunction {
+            paren,
+            arguments,
+            param_types,
+            defaults: fold_defaults(defaults),
+            rest,
+            return_type,
+            body: optimize_list(body, false),
+        },
+        Expr::Literal { .. } | Expr::Variable { .. } => expr,
+    }
+}
+
+fn fold_defaults(defaults: Vec<Option<Expr>>) -> Vec<Option<Expr>> {
+    defaults
+        .into_iter()
+        .map(|default| default.map(fold))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn optimized(source: &str) -> Vec<Stmt> {
+        optimize(parse(source).unwrap())
+    }
+
+    fn printed_literal(stmt: &Stmt) -> Option<LiteralValue> {
+        match stmt {
+            Stmt::Print {
+                expression: Expr::Literal { value },
+                ..
+            } => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        let stmts = optimized("print 2 * (3 + 4) - -1;\nprint !nil and \"a\" + \"b\";\nprint false or 3 > 2;");
+
+        let values: Vec<_> = stmts.iter().map(printed_literal).collect();
+        assert_eq!(
+            values,
+            vec![
+                Some(LiteralValue::Number(15.0)),
+                Some(LiteralValue::StringValue("ab".to_string())),
+                Some(LiteralValue::True),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_failing_operations_for_runtime() {
+        let stmts = optimized("print 1 - \"a\";");
+
+        assert!(matches!(
+            &stmts[0],
+            Stmt::Print {
+                expression: Expr::Binary { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn removes_dead_branches_and_loops() {
+        let stmts = optimized("if (false) print 1; else print 2;\nif (1 > 2) print 3;\nwhile (false) print 4;\nwhile (nil) { print 5; }");
+
+        assert_eq!(stmts.len(), 1);
+        assert_eq!(printed_literal(&stmts[0]), Some(LiteralValue::Number(2.0)));
+    }
+
+    #[test]
+    fn flattens_for_loop_blocks() {
+        let stmts = optimized("for (var i = 0; i < 3; i = i + 1) { print i; }");
+
+        let Stmt::Block { statements } = &stmts[0] else {
+            panic!("for loop with a declaration should stay a block");
+        };
+        let Stmt::WhileStmt { body, .. } = statements[1].as_ref() else {
+            panic!("expected the loop after the initializer");
+        };
+        let Stmt::Block { statements: body } = body.as_ref() else {
+            panic!("expected the loop body block");
+        };
+        assert!(matches!(body[0].as_ref(), Stmt::Print { .. }));
+        assert!(matches!(body[1].as_ref(), Stmt::Expression { .. }));
+    }
+
+    #[test]
+    fn keeps_blocks_that_declare() {
+        let stmts = optimized("var a = 1;\n{ var a = 2; print a; }\nprint a;");
+
+        assert_eq!(stmts.len(), 3);
+        assert!(matches!(&stmts[1], Stmt::Block { .. }));
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
         Token,
         TokenType::{self, *},
     },
-    stmt::{self, Stmt},
+    stmt::{self, Stmt, TestDecl},
 };
 
 use crate::expr::{Expr, Expr::*};
@@ -14,24 +14,51 @@ enum FunctionKind {
     Function,
 }
 
+/// A parsed parameter list; see `Stmt::Function`.
+struct Parameters {
+    names: Vec<Token>,
+    types: Vec<Option<Token>>,
+    defaults: Vec<Option<Expr>>,
+    rest: bool,
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    tests: Vec<TestDecl>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
         Self {
-            tokens: tokens,
+            tokens,
             current: 0,
+            tests: vec![],
         }
     }
 
+    /// Test declarations (`test "name" { ... }`) found at the top level by
+    /// the last call to `parse`. They are not part of the returned program.
+    pub fn tests(&self) -> &[TestDecl] {
+        &self.tests
+    }
+
     pub fn parse(&mut self) -> Result<Vec<Stmt>, String> {
         let mut stmts = vec![];
         let mut errs = vec![];
 
         while !self.is_at_end() {
+            if self.is_test_declaration() {
+                match self.test_declaration() {
+                    Ok(test) => self.tests.push(test),
+                    Err(msg) => {
+                        errs.push(msg);
+                        self.synchronization();
+                    }
+                }
+                continue;
+            }
+
             let stmt = self.declaration();
             match stmt {
                 Ok(s) => stmts.push(s),
@@ -41,13 +68,45 @@ impl Parser {
                 }
             }
         }
-        if errs.len() == 0 {
+        if errs.is_empty() {
             Ok(stmts)
         } else {
             Err(errs.join("\n"))
         }
     }
 
+    // `test` is only special when followed by a string, so it stays usable
+    // as an ordinary identifier.
+    fn is_test_declaration(&self) -> bool {
+        let token = self.peek();
+        token.token_type == TokenType::Identifier
+            && token.lexeme == "test"
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|next| next.token_type == TokenType::StringLit)
+    }
+
+    fn test_declaration(&mut self) -> Result<TestDecl, String> {
+        let keyword = self.advance();
+        let name = match LiteralValue::from_token(&self.advance()) {
+            LiteralValue::StringValue(name) => name,
+            _ => panic!("Test name was not a string literal"),
+        };
+
+        self.consume(TokenType::LeftBrace, "Expected '{' after test name.")?;
+        let body = match self.block_statement()? {
+            Stmt::Block { statements } => statements,
+            _ => panic!("Block statement parsed something that was not a block"),
+        };
+
+        Ok(TestDecl {
+            keyword,
+            name,
+            body,
+        })
+    }
+
     fn declaration(&mut self) -> Result<Stmt, String> {
         if self.match_token(&TokenType::Var) {
             self.var_declaration()
@@ -66,18 +125,71 @@ impl Parser {
             &format!("Expected '(' after {kind:?} name"),
         )?;
 
-        let mut parameters = vec![];
+        let parameters = self.parameters()?;
+        let return_type = self.type_annotation()?;
+
+        self.consume(TokenType::LeftBrace, "Expected '{{' before {kind:?} body.")?;
+        let body = match self.block_statement()? {
+            Stmt::Block { statements } => statements,
+            _ => panic!("Block statement parsed something that was not a block"),
+        };
+
+        Ok(Stmt::Function {
+            name,
+            params: parameters.names,
+            param_types: parameters.types,
+            defaults: parameters.defaults,
+            rest: parameters.rest,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses a parameter list up to and including the closing paren. Each
+    /// parameter may carry a `: Type` annotation and an `= default`, and the
+    /// last one may be `...rest`.
+    fn parameters(&mut self) -> Result<Parameters, String> {
+        let mut parameters = Parameters {
+            names: vec![],
+            types: vec![],
+            defaults: vec![],
+            rest: false,
+        };
         if !self.check(TokenType::RightParen) {
             loop {
-                if parameters.len() >= 255 {
+                if parameters.names.len() >= 255 {
                     let location = self.peek().lineNumber;
                     return Err(format!(
                         "Line {location}: Cant have more than 255 arguments"
                     ));
                 }
+                if parameters.rest {
+                    return Err(self.error(&self.peek(), "Rest parameter must be the last one"));
+                }
 
+                parameters.rest = self.match_token(&TokenType::DotDotDot);
                 let param = self.consume(TokenType::Identifier, "Expected parameter name")?;
-                parameters.push(param);
+                parameters.types.push(self.type_annotation()?);
+                let default = if self.match_token(&TokenType::Equal) {
+                    if parameters.rest {
+                        return Err(self.error(&param, "Rest parameter can't have a default"));
+                    }
+                    Some(self.expression()?)
+                } else {
+                    Option::None
+                };
+                let after_default = parameters.defaults.iter().any(|d| d.is_some());
+                if default.is_none() && after_default && !parameters.rest {
+                    return Err(self.error(
+                        &param,
+                        &format!(
+                            "Parameter '{}' needs a default since an earlier one has one",
+                            param.lexeme
+                        ),
+                    ));
+                }
+                parameters.names.push(param);
+                parameters.defaults.push(default);
 
                 if !self.match_token(&TokenType::Comma) {
                     break;
@@ -85,31 +197,29 @@ impl Parser {
             }
         }
         self.consume(TokenType::RightParen, "Expected ')' after paramters.")?;
+        Ok(parameters)
+    }
 
-        self.consume(TokenType::LeftBrace, "Expected '{{' before {kind:?} body.")?;
-        let body = match self.block_statement()? {
-            Stmt::Block { statements } => statements,
-            _ => panic!("Block statement parsed something that was not a block"),
-        };
-
-        return Ok(Stmt::Function {
-            name,
-            params: parameters,
-            body,
-        });
+    /// Parses an optional `: Type` annotation and returns the type name.
+    fn type_annotation(&mut self) -> Result<Option<Token>, String> {
+        if self.match_token(&TokenType::Colon) {
+            Ok(Some(self.consume(TokenType::Identifier, "Expected type name after ':'")?))
+        } else {
+            Ok(Option::None)
+        }
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, String> {
         let token = self.consume(TokenType::Identifier, "Expected variable name")?;
+        let type_annotation = self.type_annotation()?;
 
-        let initializer;
-        if self.match_token(&TokenType::Equal) {
-            initializer = self.expression()?;
+        let initializer = if self.match_token(&TokenType::Equal) {
+            self.expression()?
         } else {
-            initializer = Literal {
+            Literal {
                 value: LiteralValue::Nil,
-            };
-        }
+            }
+        };
 
         self.consume(
             TokenType::Semicolon,
@@ -118,7 +228,8 @@ impl Parser {
 
         Ok(Stmt::Var {
             name: token,
-            initializer: initializer,
+            type_annotation,
+            initializer,
         })
     }
 
@@ -142,25 +253,29 @@ impl Parser {
 
     fn return_statement(&mut self) -> Result<Stmt, String> {
         let keyword = self.previous();
-        let value;
-        if !self.check(TokenType::Semicolon) {
-            value = Some(self.expression()?);
+        let value = if !self.check(TokenType::Semicolon) {
+            Some(self.expression()?)
         } else {
-            value = Option::None;
-        }
+            Option::None
+        };
         self.consume(TokenType::Semicolon, "Expected ';' after return value")?;
 
         Ok(Stmt::ReturnStmt {
             keyword,
-            value: value,
+            value,
         })
     }
 
     fn for_statement(&mut self) -> Result<Stmt, String> {
         // for v
         //          ( SMTH; SMTH; SMTH )
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expected '(' after 'for'")?;
 
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+            return self.for_in_statement(keyword);
+        }
+
         let initializer: Option<Stmt>;
         if self.match_token(&TokenType::Semicolon) {
             initializer = Option::None;
@@ -172,22 +287,20 @@ impl Parser {
             initializer = Some(expr);
         }
 
-        let condition;
-        if !self.check(TokenType::Semicolon) {
+        let condition = if !self.check(TokenType::Semicolon) {
             let expr = self.comparison()?;
-            condition = Some(expr)
+            Some(expr)
         } else {
-            condition = Option::None;
-        }
+            Option::None
+        };
         self.consume(TokenType::Semicolon, "Expected ';' after loop condition")?;
 
-        let increment;
-        if !self.check(TokenType::RightParen) {
+        let increment = if !self.check(TokenType::RightParen) {
             let expr = self.expression()?;
-            increment = Some(expr)
+            Some(expr)
         } else {
-            increment = Option::None;
-        }
+            Option::None
+        };
         self.consume(TokenType::RightParen, "Expected ')' after for clause")?;
 
         let mut body = self.statement()?;
@@ -201,16 +314,14 @@ impl Parser {
             }
         }
 
-        let cond;
-        match condition {
-            Option::None => {
-                cond = Expr::Literal {
-                    value: LiteralValue::True,
-                }
-            }
-            Some(c) => cond = c,
-        }
+        let cond = match condition {
+            Option::None => Expr::Literal {
+                value: LiteralValue::True,
+            },
+            Some(c) => c,
+        };
         body = Stmt::WhileStmt {
+            keyword,
             condition: cond,
             body: Box::new(body),
         };
@@ -224,19 +335,38 @@ impl Parser {
         Ok(body)
     }
 
+    /// Parses the rest of `for (x in iterable) body`, after the '('.
+    fn for_in_statement(&mut self, keyword: Token) -> Result<Stmt, String> {
+        let variable = self.advance();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after for clause")?;
+        let body = self.statement()?;
+
+        Ok(Stmt::ForIn {
+            keyword,
+            variable,
+            iterable,
+            body: Box::new(body),
+        })
+    }
+
     fn while_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Exptected ')' after condition.")?;
         let body = self.statement()?;
 
         Ok(Stmt::WhileStmt {
+            keyword,
             condition,
             body: Box::new(body),
         })
     }
 
     fn if_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expected '(' after 'if'")?;
         let predicate = self.expression()?;
         self.consume(TokenType::RightParen, "Expected ')' after if-predicate")?;
@@ -250,6 +380,7 @@ impl Parser {
         };
 
         Ok(Stmt::IfStmt {
+            keyword,
             predicate,
             then,
             els,
@@ -269,9 +400,13 @@ impl Parser {
     }
 
     fn print_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expected ';' after value.")?;
-        Ok(Stmt::Print { expression: value })
+        Ok(Stmt::Print {
+            keyword,
+            expression: value,
+        })
     }
 
     fn expression_statement(&mut self) -> Result<Stmt, String> {
@@ -290,25 +425,8 @@ impl Parser {
             "Expected '(' after anonymous function",
         )?;
 
-        let mut parameters = vec![];
-        if !self.check(TokenType::RightParen) {
-            loop {
-                if parameters.len() >= 255 {
-                    let location = self.peek().lineNumber;
-                    return Err(format!(
-                        "Line {location}: Cant have more than 255 arguments"
-                    ));
-                }
-
-                let param = self.consume(TokenType::Identifier, "Expected parameter name")?;
-                parameters.push(param);
-
-                if !self.match_token(&TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-        self.consume(TokenType::RightParen, "Expected ')' after paramters.")?;
+        let parameters = self.parameters()?;
+        let return_type = self.type_annotation()?;
 
         self.consume(
             TokenType::LeftBrace,
@@ -321,12 +439,106 @@ impl Parser {
         };
         Ok(Expr::AnonFunction {
             paren,
-            arguments: parameters,
+            arguments: parameters.names,
+            param_types: parameters.types,
+            defaults: parameters.defaults,
+            rest: parameters.rest,
+            return_type,
+            body,
+        })
+    }
+
+    /// Whether the next tokens start an arrow lambda: `x =>` or a
+    /// parenthesized parameter list (with an optional return type) followed
+    /// by `=>`.
+    fn is_arrow_function(&self) -> bool {
+        match self.peek().token_type {
+            TokenType::Identifier => self.check_next(TokenType::Arrow),
+            LeftParen => {
+                let mut depth = 0;
+                let mut i = self.current;
+                loop {
+                    match self.tokens[i].token_type {
+                        LeftParen => depth += 1,
+                        TokenType::RightParen => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        TokenType::Eof => return false,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                let after: Vec<TokenType> = self.tokens[i + 1..]
+                    .iter()
+                    .take(3)
+                    .map(|token| token.token_type)
+                    .collect();
+                matches!(
+                    after.as_slice(),
+                    [TokenType::Arrow, ..]
+                        | [TokenType::Colon, TokenType::Identifier, TokenType::Arrow]
+                )
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses `x => expr`, `(a, b) => expr` or `(a, b) => { ... }` into an
+    /// anonymous function. A block body returns the value of its last
+    /// statement if that is an expression statement.
+    fn arrow_function(&mut self) -> Result<Expr, String> {
+        let parameters = if self.match_token(&LeftParen) {
+            self.parameters()?
+        } else {
+            Parameters {
+                names: vec![self.advance()],
+                types: vec![Option::None],
+                defaults: vec![Option::None],
+                rest: false,
+            }
+        };
+        let return_type = self.type_annotation()?;
+        let arrow = self.consume(TokenType::Arrow, "Expected '=>' after lambda parameters")?;
+
+        let body = if self.match_token(&TokenType::LeftBrace) {
+            let mut body = match self.block_statement()? {
+                Stmt::Block { statements } => statements,
+                _ => panic!("Block statement parsed something that was not a block"),
+            };
+            if let Some(last) = body.last_mut() {
+                if let Stmt::Expression { expression } = last.as_ref() {
+                    **last = Stmt::ReturnStmt {
+                        keyword: arrow.clone(),
+                        value: Some(expression.clone()),
+                    };
+                }
+            }
+            body
+        } else {
+            vec![Box::new(Stmt::ReturnStmt {
+                keyword: arrow.clone(),
+                value: Some(self.expression()?),
+            })]
+        };
+
+        Ok(Expr::AnonFunction {
+            paren: arrow,
+            arguments: parameters.names,
+            param_types: parameters.types,
+            defaults: parameters.defaults,
+            rest: parameters.rest,
+            return_type,
             body,
         })
     }
 
     fn assignment(&mut self) -> Result<Expr, String> {
+        if self.is_arrow_function() {
+            return self.arrow_function();
+        }
         let expr = self.or()?;
 
         if self.match_token(&TokenType::Equal) {
@@ -337,7 +549,7 @@ impl Parser {
                     name,
                     value: Box::from(value),
                 }),
-                _ => Err("Invalid argument target".to_string()),
+                _ => Err(self.error(&self.previous(), "Invalid assignment target")),
             }
         } else {
             Ok(expr)
@@ -353,7 +565,7 @@ impl Parser {
 
             expr = Logical {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(right),
             };
         }
@@ -369,7 +581,7 @@ impl Parser {
             let right = self.equality()?;
             expr = Logical {
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(right),
             };
         }
@@ -392,22 +604,38 @@ impl Parser {
             // matches_eq = self.match_tokens(&[BangEqual, EqualEqual]);
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn comparison(&mut self) -> Result<Expr, String> {
-        let mut expr = self.term()?;
+        let mut expr = self.range()?;
 
         while self.match_tokens(&[Greater, GreaterEqual, Less, LessEqual]) {
             let op = self.previous();
-            let rhs = self.term()?;
+            let rhs = self.range()?;
             expr = Binary {
                 left: Box::from(expr),
                 operator: op,
                 right: Box::from(rhs),
             };
         }
-        return Ok(expr);
+        Ok(expr)
+    }
+
+    /// Parses `start..end` or `start..=end`. Ranges don't chain.
+    fn range(&mut self) -> Result<Expr, String> {
+        let expr = self.term()?;
+
+        if self.match_tokens(&[DotDot, DotDotEqual]) {
+            let op = self.previous();
+            let rhs = self.term()?;
+            return Ok(Binary {
+                left: Box::from(expr),
+                operator: op,
+                right: Box::from(rhs),
+            });
+        }
+        Ok(expr)
     }
 
     fn term(&mut self) -> Result<Expr, String> {
@@ -423,7 +651,7 @@ impl Parser {
             };
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn factor(&mut self) -> Result<Expr, String> {
@@ -438,7 +666,7 @@ impl Parser {
             }
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn match_token(&mut self, typ: &TokenType) -> bool {
@@ -447,9 +675,9 @@ impl Parser {
         } else {
             if self.peek().token_type == *typ {
                 self.advance();
-                return true;
+                true
             } else {
-                return false;
+                false
             }
         }
     }
@@ -460,7 +688,7 @@ impl Parser {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     fn advance(&mut self) -> Token {
@@ -468,7 +696,7 @@ impl Parser {
             self.current += 1;
         }
 
-        return self.previous();
+        self.previous()
     }
 
     fn peek(&self) -> Token {
@@ -503,12 +731,30 @@ impl Parser {
 
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
         let mut arguments = vec![];
+        let mut named: Vec<(Token, Expr)> = vec![];
 
         if !self.check(TokenType::RightParen) {
             loop {
-                let arg = self.expression()?;
-                arguments.push(arg);
-                if arguments.len() >= 255 {
+                if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+                    let name = self.advance();
+                    self.advance();
+                    if named.iter().any(|(other, _)| other.lexeme == name.lexeme) {
+                        return Err(self.error(
+                            &name,
+                            &format!("Argument '{}' is passed twice", name.lexeme),
+                        ));
+                    }
+                    named.push((name, self.expression()?));
+                } else if !named.is_empty() {
+                    return Err(self.error(
+                        &self.peek(),
+                        "Positional arguments must come before named ones",
+                    ));
+                } else {
+                    let arg = self.expression()?;
+                    arguments.push(arg);
+                }
+                if arguments.len() + named.len() >= 255 {
                     let location = self.peek().lineNumber;
                     return Err(format!(
                         "Line {location}: Cant have more than 255 arguments"
@@ -526,21 +772,21 @@ impl Parser {
             callee: Box::new(callee),
             paren,
             arguments,
+            named,
         })
     }
 
     fn primary(&mut self) -> Result<Expr, String> {
         let token = self.peek();
 
-        let result;
-        match token.token_type {
+        let result = match token.token_type {
             LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
                 self.consume(TokenType::RightParen, "Expected ')'")?;
-                result = Grouping {
+                Grouping {
                     expression: Box::from(expr),
-                };
+                }
             }
             TokenType::False
             | TokenType::True
@@ -548,24 +794,24 @@ impl Parser {
             | TokenType::Number
             | TokenType::StringLit => {
                 self.advance();
-                result = Literal {
+                Literal {
                     value: LiteralValue::from_token(&token),
                 }
             }
             TokenType::Identifier => {
                 self.advance();
-                result = Variable {
+                Variable {
                     name: self.previous(),
-                };
+                }
             }
             TokenType::Fun => {
                 self.advance();
-                result = self.function_expression()?;
+                self.function_expression()?
             },
-            _ => return Err("Expected expression".to_string()),
-        }
+            _ => return Err(self.error(&token, "Expected expression")),
+        };
 
-        return Ok(result);
+        Ok(result)
     }
 
     fn consume(&mut self, token_type: TokenType, msg: &str) -> Result<Token, String> {
@@ -575,14 +821,24 @@ impl Parser {
             let token = self.previous();
             Ok(token)
         } else {
-            Err(msg.to_string())
+            Err(self.error(&token, msg))
         }
     }
 
+    fn error(&self, token: &Token, msg: &str) -> String {
+        format!("Line {}: {}", token.lineNumber, msg)
+    }
+
     fn check(&mut self, typ: TokenType) -> bool {
         self.peek().token_type == typ
     }
 
+    fn check_next(&self, typ: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == typ)
+    }
+
     fn previous(&self) -> Token {
         self.tokens[self.current - 1].clone()
     }
@@ -610,6 +866,7 @@ impl Parser {
                 | TokenType::Return => return,
                 _ => (),
             }
+            self.advance();
         }
     }
 }
@@ -629,30 +886,35 @@ mod tests {
             lexeme: "1".to_string(),
             literal: Some(FValue(1.0)),
             lineNumber: 0,
+            column: 0,
         };
         let plus = Token {
             token_type: Plus,
             lexeme: "+".to_string(),
             literal: Option::None,
             lineNumber: 0,
+            column: 0,
         };
         let two = Token {
             token_type: Number,
             lexeme: "2".to_string(),
             literal: Some(FValue(2.0)),
             lineNumber: 0,
+            column: 0,
         };
         let semicolon = Token {
             token_type: Semicolon,
             lexeme: ";".to_string(),
             literal: Option::None,
             lineNumber: 0,
+            column: 0,
         };
         let eof = Token {
             token_type: Eof,
             lexeme: "".to_string(),
             literal: Option::None,
             lineNumber: 0,
+            column: 0,
         };
 
         let tokens = vec![one, plus, two, semicolon, eof];
@@ -691,4 +953,97 @@ mod tests {
 
         assert_eq!(string_expr, "(== 1 (group (+ 2 2)))");
     }
+
+    #[test]
+    fn test_statement_rendering() {
+        let source = "fun f(a, b) { if (a) return b; else return; }\nwhile (true) x = f(1, 2);";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse().unwrap();
+        let rendered: Vec<String> = stmts.iter().map(|stmt| stmt.to_string()).collect();
+
+        assert_eq!(
+            rendered,
+            vec![
+                "(fun f (a b) (if (var a) (return (var b)) (return)))",
+                "(while true (= x (call (var f) 1 2)))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameters_and_named_arguments() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let stmts = parse("fun f(a, b = a + 1, ...rest) {}
+f(1, b: 2);").unwrap();
+        let rendered: Vec<String> = stmts.iter().map(|stmt| stmt.to_string()).collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "(fun f (a b=(+ (var a) 1) ...rest) )",
+                "(call (var f) 1 b: 2)",
+            ]
+        );
+
+        for (source, error) in [
+            ("fun f(...a, b) {}", "Line 1: Rest parameter must be the last one"),
+            ("fun f(...a = 1) {}", "Line 1: Rest parameter can't have a default"),
+            (
+                "fun f(a = 1, b) {}",
+                "Line 1: Parameter 'b' needs a default since an earlier one has one",
+            ),
+            ("f(a: 1, 2);", "Line 1: Positional arguments must come before named ones"),
+            ("f(a: 1, a: 2);", "Line 1: Argument 'a' is passed twice"),
+        ] {
+            assert_eq!(parse(source).err(), Some(error.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_arrow_functions() {
+        let tokens = Scanner::new("x => x * 2;\n(a, b) => { print a; a + b; };\n(a) + 1;")
+            .scan_tokens()
+            .unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+
+        let bodies: Vec<Vec<String>> = stmts[..2]
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Expression {
+                    expression: Expr::AnonFunction { body, .. },
+                } => body.iter().map(|stmt| stmt.to_string()).collect(),
+                _ => panic!("Expected an arrow function, got {}", stmt.to_string()),
+            })
+            .collect();
+        assert_eq!(
+            bodies,
+            vec![
+                vec!["(return (* (var x) 2))"],
+                vec!["(print (var a))", "(return (+ (var a) (var b)))"],
+            ]
+        );
+        assert_eq!(stmts[2].to_string(), "(+ (group (var a)) 1)");
+    }
+
+    #[test]
+    fn test_for_in_and_ranges() {
+        let tokens = Scanner::new("for (i in 0..n + 1) print i;\nvar r = 1..=2 < 3;")
+            .scan_tokens()
+            .unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let rendered: Vec<String> = stmts.iter().map(|stmt| stmt.to_string()).collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "(for i (.. 0 (+ (var n) 1)) (print (var i)))",
+                "(var r (< (..= 1 2) 3))",
+            ]
+        );
+    }
 }
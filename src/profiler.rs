@@ -0,0 +1,211 @@
+use crate::debugger::DebugHook;
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// What the profiler measured for one function name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    /// Time from entering the function until it returned, including the
+    /// functions it called. Recursive calls are only counted once.
+    pub inclusive: Duration,
+    /// Time spent in the function's own statements.
+    pub exclusive: Duration,
+}
+
+struct Call {
+    name: String,
+    started: Instant,
+    /// Inclusive time of the calls made from this one.
+    children: Duration,
+}
+
+/// An instrumenting profiler installed as the interpreter's debug hook. It
+/// times every Bolt function call by name; anonymous functions are named
+/// after the line of their parameter list, like `<anon line 3>`.
+pub struct Profiler {
+    stack: Vec<Call>,
+    stats: HashMap<String, FunctionStats>,
+    /// Exclusive time per call stack, keyed by the `;`-joined frame names.
+    folded: HashMap<String, Duration>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Starts timing the top-level script.
+    pub fn new() -> Self {
+        Self {
+            stack: vec![Call {
+                name: "<script>".to_string(),
+                started: Instant::now(),
+                children: Duration::ZERO,
+            }],
+            stats: HashMap::new(),
+            folded: HashMap::new(),
+        }
+    }
+
+    fn pop(&mut self) {
+        let Some(call) = self.stack.pop() else {
+            return;
+        };
+        let inclusive = call.started.elapsed();
+        let exclusive = inclusive.saturating_sub(call.children);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += inclusive;
+        }
+
+        let path: Vec<&str> = self
+            .stack
+            .iter()
+            .map(|c| c.name.as_str())
+            .chain([call.name.as_str()])
+            .collect();
+        *self.folded.entry(path.join(";")).or_default() += exclusive;
+
+        if self.stack.is_empty() {
+            return;
+        }
+        let recursive = self.stack.iter().any(|c| c.name == call.name);
+        let stats = self.stats.entry(call.name).or_default();
+        stats.calls += 1;
+        stats.exclusive += exclusive;
+        if !recursive {
+            stats.inclusive += inclusive;
+        }
+    }
+
+    /// Stops timing the script. Call once the program has finished.
+    pub fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.pop();
+        }
+    }
+
+    pub fn stats(&self) -> &HashMap<String, FunctionStats> {
+        &self.stats
+    }
+
+    /// A table of every function, slowest (by inclusive time) first.
+    pub fn report(&self) -> String {
+        let mut rows: Vec<(&String, &FunctionStats)> = self.stats.iter().collect();
+        rows.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(b.0)));
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(8);
+        let mut report = format!(
+            "{:<width$} {:>8} {:>14} {:>14}\n",
+            "function", "calls", "inclusive ms", "exclusive ms"
+        );
+        for (name, stats) in rows {
+            report += &format!(
+                "{:<width$} {:>8} {:>14.3} {:>14.3}\n",
+                name,
+                stats.calls,
+                stats.inclusive.as_secs_f64() * 1000.0,
+                stats.exclusive.as_secs_f64() * 1000.0
+            );
+        }
+        report
+    }
+
+    /// The folded-stack format read by flamegraph tools: one line per call
+    /// stack with its exclusive time in microseconds.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+impl DebugHook for Profiler {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        self.stack.push(Call {
+            name: name.to_string(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+        Ok(())
+    }
+
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {
+        // The base frame belongs to the script and is only closed by `finish`.
+        if self.stack.len() > 1 {
+            self.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_unoptimized;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn profile(source: &str) -> Rc<RefCell<Profiler>> {
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut interpreter = Interpreter::with_output(
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(Vec::new())),
+        );
+        interpreter.set_debug_hook(profiler.clone());
+        run_unoptimized(&mut interpreter, source).unwrap();
+        profiler.borrow_mut().finish();
+        profiler
+    }
+
+    #[test]
+    fn counts_calls_by_name() {
+        let profiler = profile(
+            "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\nvar twice = fun (x) {\n  return x * 2;\n};\nprint twice(fib(6));\nprint twice(1);\n",
+        );
+
+        let profiler = profiler.borrow();
+        let stats = profiler.stats();
+        assert_eq!(stats["fib"].calls, 25);
+        assert_eq!(stats["<anon line 5>"].calls, 2);
+        assert_eq!(stats.len(), 2);
+        for stats in stats.values() {
+            assert!(stats.exclusive <= stats.inclusive);
+        }
+        assert!(profiler.report().starts_with("function "));
+    }
+
+    #[test]
+    fn folds_call_stacks() {
+        let profiler = profile("fun inner() {}\nfun outer() { inner(); inner(); }\nouter();\ninner();\n");
+
+        let profiler = profiler.borrow();
+        let folded = profiler.folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            vec!["<script>", "<script>;inner", "<script>;outer", "<script>;outer;inner"]
+        );
+        assert_eq!(profiler.stats()["inner"].calls, 3);
+    }
+}
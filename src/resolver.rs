@@ -1,13 +1,66 @@
-use crate::expr::Expr;
+use crate::expr::{Arity, Expr};
 use crate::interpreter::Interpreter;
+use crate::lint::Warning;
 use crate::scanner::Token;
 use crate::stmt::Stmt;
 use core::panic;
 use std::collections::HashMap;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Parameter,
+}
+
+/// A declaration seen by the resolver.
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: Token,
+    pub kind: SymbolKind,
+    /// Parameters of a function declaration, empty for other kinds.
+    pub params: Vec<Token>,
+    /// Arity of a function declaration.
+    pub arity: Arity,
+    /// Index of the innermost named function the symbol is declared in.
+    pub container: Option<usize>,
+}
+
+/// A use of a name (read or assignment) and the symbol it resolved to, if
+/// any. Names without a declaration in the program (natives, typos) have no
+/// symbol.
+#[derive(Clone)]
+pub struct Reference {
+    pub name: Token,
+    pub symbol: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    defined: bool,
+    /// Whether the value is ever read. Assigning to a name is not a use.
+    used: bool,
+    symbol: usize,
+}
+
 pub struct Resolver {
     interpreter: Interpreter,
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, Binding>>,
+    globals: HashMap<String, usize>,
+    current_function: Option<usize>,
+    /// How many function bodies (named or anonymous) enclose the code being
+    /// resolved.
+    function_depth: usize,
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+    /// Lint warnings noticed along the way, in no particular order.
+    pub warnings: Vec<Warning>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Resolver {
@@ -15,7 +68,30 @@ impl Resolver {
         Self {
             interpreter: Interpreter::new(),
             scopes: vec![],
+            globals: HashMap::new(),
+            current_function: None,
+            function_depth: 0,
+            symbols: vec![],
+            references: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Resolves a whole program. Uses of globals are linked once every
+    /// statement has been seen, since functions may refer to globals that
+    /// are declared after them.
+    pub fn resolve_program(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.warn_unreachable(stmts.iter());
+        for stmt in stmts {
+            self.resolve(stmt)?;
         }
+
+        for reference in self.references.iter_mut() {
+            if reference.symbol.is_none() {
+                reference.symbol = self.globals.get(&reference.name.lexeme).copied();
+            }
+        }
+        Ok(())
     }
 
     pub fn scopes_is_empty(&self) -> bool {
@@ -25,27 +101,44 @@ impl Resolver {
     pub fn resolve(&mut self, stmt: &Stmt) -> Result<(), String> {
         match stmt {
             Stmt::Block { statements: _ } => self.resolve_block(stmt)?,
-            Stmt::Var {
-                name: _,
-                initializer: _,
-            } => self.resolve_var(stmt)?,
-            Stmt::Function { name: _, params: _, body: _ } => self.resolve_function(stmt)?,
+            Stmt::Var { .. } => self.resolve_var(stmt)?,
+            Stmt::Function { .. } => self.resolve_function(stmt)?,
             Stmt::Expression { expression } => self.resolve_expr(expression)?,
-            Stmt::IfStmt {
-                predicate: _,
-                then: _,
-                els: _,
-            } => self.resolve_if_stmt(stmt)?,
-            Stmt::Print { expression } => self.resolve_expr(expression)?,
-            Stmt::ReturnStmt { keyword: _, value: None } => (),
-            Stmt::ReturnStmt { keyword: _, value: Some(value) } => self.resolve_expr(value)?,
-            Stmt::WhileStmt { condition, body } => {
+            Stmt::IfStmt { .. } => self.resolve_if_stmt(stmt)?,
+            Stmt::Print { expression, .. } => self.resolve_expr(expression)?,
+            Stmt::ReturnStmt { keyword, value } => {
+                if self.function_depth == 0 {
+                    self.warn(
+                        "top-level-return",
+                        keyword.lineNumber,
+                        "Can't return from top-level code".to_string(),
+                    );
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value)?;
+                }
+            }
+            Stmt::WhileStmt {
+                condition, body, ..
+            } => {
                 self.resolve_expr(condition)?;
                 self.resolve(body.as_ref())?;
             }
-            _ => todo!(),
+            Stmt::ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.resolve_expr(iterable)?;
+                self.begin_scope();
+                self.declare(variable);
+                self.define(variable.clone());
+                self.resolve(body.as_ref())?;
+                self.end_scope();
+            }
         }
-        todo!()
+        Ok(())
     }
 
     pub fn resolve_expr(&mut self, expr: &Expr) -> Result<(), String> {
@@ -56,38 +149,44 @@ impl Resolver {
                 self.resolve_expr(left)?;
                 self.resolve_expr(right)
             },
-            Expr::Call { callee, paren, arguments } => {
+            Expr::Call { callee, paren, arguments, named } => {
                 self.resolve_expr(callee.as_ref())?;
                 for arg in arguments {
                     self.resolve_expr(arg)?;
                 }
+                for (_, arg) in named {
+                    self.resolve_expr(arg)?;
+                }
                 Ok(())
             },
-            Expr::Grouping { expression } => self.resolve_expr(&expression),
+            Expr::Grouping { expression } => self.resolve_expr(expression),
             Expr::Literal { value: _ } => Ok(()),
             Expr::Logical { left, operator: _, right } => {
                 self.resolve_expr(left)?;
                 self.resolve_expr(right)
             },
             Expr::Unary { operator: _, right } => self.resolve_expr(right),
-            Expr::AnonFunction { paren: _, arguments, body } => {
-                self.resolve_function_helper(arguments, body)
+            Expr::AnonFunction {
+                arguments, defaults, body, ..
+            } => {
+                self.resolve_function_helper(arguments, defaults, body)
             }
-            _ => todo!(),
         }
     }
 
     pub fn resolve_expr_var(&mut self, expr: &Expr) -> Result<(), String> {
         if let Expr::Variable { name } = expr {
             if !self.scopes_is_empty()
-                && *self.scopes[self.scopes.len() - 1]
+                && self.scopes[self.scopes.len() - 1]
                     .get(&name.lexeme)
-                    .unwrap()
-                    == false
+                    .is_some_and(|binding| !binding.defined)
             {
-                return Err("Can't read local variable on its own initializer".to_string());
+                return Err(format!(
+                    "Line {}: Can't read local variable on its own initializer",
+                    name.lineNumber
+                ));
             }
-            self.resolve_local(&expr, name)?
+            self.resolve_local(expr, name)?
         } else {
             panic!("Wrong type in resolver_expr_var");
         }
@@ -106,17 +205,28 @@ impl Resolver {
 
     fn resolve_local(&mut self, expr: &Expr, name: &Token) -> Result<(), String> {
         let size = self.scopes.len();
-        for i in (0..=(size - 1)).rev() {
-            let scope = &self.scopes[i];
-            if scope.contains_key(&name.lexeme) {
+        let is_read = matches!(expr, Expr::Variable { .. });
+        for i in (0..size).rev() {
+            let scope = &mut self.scopes[i];
+            if let Some(binding) = scope.get_mut(&name.lexeme) {
+                binding.used |= is_read;
+                self.references.push(Reference {
+                    name: name.clone(),
+                    symbol: Some(binding.symbol),
+                });
                 self.interpreter.resolve(expr, size - 1 - i)?;
                 return Ok(());
             }
         }
+        self.references.push(Reference {
+            name: name.clone(),
+            symbol: None,
+        });
         Ok(())
     }
 
     fn resolve_many(&mut self, stmts: Vec<Box<Stmt>>) -> Result<(), String> {
+        self.warn_unreachable(stmts.iter().map(|stmt| stmt.as_ref()));
         for stmt in stmts {
             self.resolve(stmt.as_ref())?;
         }
@@ -124,11 +234,18 @@ impl Resolver {
     }
 
     fn resolve_function(&mut self, stmt: &Stmt) -> Result<(), String> {
-        if let Stmt::Function { name, params, body } = stmt {
-            self.declare(name);
+        if let Stmt::Function {
+            name, params, defaults, rest, body, ..
+        } = stmt {
+            let symbol = self.declare_kind(name, SymbolKind::Function);
+            self.symbols[symbol].params = params.clone();
+            self.symbols[symbol].arity = Arity::of(defaults, *rest);
             self.define(name.clone());
 
-            self.resolve_function_helper(params, body)
+            let enclosing = self.current_function.replace(symbol);
+            let result = self.resolve_function_helper(params, defaults, body);
+            self.current_function = enclosing;
+            result
         } else {
             panic!("Wrong type in resolve var");
         }
@@ -139,6 +256,7 @@ impl Resolver {
             predicate,
             then,
             els,
+            ..
         } = stmt
         {
             self.resolve_expr(predicate)?;
@@ -152,19 +270,28 @@ impl Resolver {
         }
     }
 
-    fn resolve_function_helper(&mut self, params: &Vec<Token>, body: &Vec<Box<Stmt>>) -> Result<(), String> {
+    fn resolve_function_helper(&mut self, params: &[Token], defaults: &[Option<Expr>], body: &Vec<Box<Stmt>>) -> Result<(), String> {
             self.begin_scope();
-            for param in params {
-                self.declare(param);
+            for (param, default) in params.iter().zip(defaults) {
+                // A default can refer to the parameters before it.
+                if let Some(default) = default {
+                    self.resolve_expr(default)?;
+                }
+                self.declare_kind(param, SymbolKind::Parameter);
                 self.define((*param).clone());
             }
-            self.resolve_many((*body).clone())?;
+            self.function_depth += 1;
+            let result = self.resolve_many((*body).clone());
+            self.function_depth -= 1;
+            result?;
             self.end_scope();
             Ok(())
     }
 
     fn resolve_var(&mut self, stmt: &Stmt) -> Result<(), String> {
-        if let Stmt::Var { name, initializer } = stmt {
+        if let Stmt::Var {
+            name, initializer, ..
+        } = stmt {
             self.declare(name);
             self.resolve_expr(initializer)?;
             self.define(name.clone());
@@ -192,16 +319,112 @@ impl Resolver {
     }
 
     fn end_scope(&mut self) {
-        self.scopes.pop().expect("Stack underflow");
+        let scope = self.scopes.pop().expect("Stack underflow");
+        for (name, binding) in scope {
+            self.warn_unused(&name, binding);
+        }
+    }
+
+    fn warn_unused(&mut self, name: &str, binding: Binding) {
+        if binding.used || name.starts_with('_') {
+            return;
+        }
+        let symbol = &self.symbols[binding.symbol];
+        let (rule, what) = match symbol.kind {
+            SymbolKind::Parameter => ("unused-parameter", "Parameter"),
+            SymbolKind::Function => ("unused-variable", "Function"),
+            SymbolKind::Variable => ("unused-variable", "Variable"),
+        };
+        let line = symbol.name.lineNumber;
+        self.warn(rule, line, format!("{} '{}' is never used", what, name));
+    }
+
+    fn warn(&mut self, rule: &'static str, line: usize, message: String) {
+        self.warnings.push(Warning { rule, line, message });
+    }
+
+    /// Flags the first statement that follows a `return` in the same list.
+    fn warn_unreachable<'a>(&mut self, stmts: impl Iterator<Item = &'a Stmt>) {
+        let mut return_line = None;
+        for stmt in stmts {
+            if let Some(line) = return_line {
+                let message = format!("Unreachable code after return on line {}", line);
+                self.warn("unreachable-code", stmt.line().unwrap_or(line), message);
+                return;
+            }
+            if let Stmt::ReturnStmt { keyword, .. } = stmt {
+                return_line = Some(keyword.lineNumber);
+            }
+        }
+    }
+
+    /// Warns when `name` is declared twice in the innermost scope or hides a
+    /// name from an enclosing scope.
+    fn check_declaration(&mut self, name: &Token) {
+        let size = self.scopes.len();
+        let innermost = match self.scopes.last() {
+            Some(scope) => scope.get(&name.lexeme).map(|binding| binding.symbol),
+            None => self.globals.get(&name.lexeme).copied(),
+        };
+        if let Some(symbol) = innermost {
+            let message = format!(
+                "'{}' is already declared in this scope on line {}",
+                name.lexeme, self.symbols[symbol].name.lineNumber
+            );
+            self.warn("duplicate-declaration", name.lineNumber, message);
+            return;
+        }
+        if size == 0 {
+            return;
+        }
+
+        let outer = self.scopes[..size - 1]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme).map(|binding| binding.symbol))
+            .or_else(|| self.globals.get(&name.lexeme).copied());
+        if let Some(symbol) = outer {
+            let message = format!(
+                "'{}' shadows a declaration on line {}",
+                name.lexeme, self.symbols[symbol].name.lineNumber
+            );
+            self.warn("shadowing", name.lineNumber, message);
+        }
     }
 
     fn declare(&mut self, name: &Token) {
+        self.declare_kind(name, SymbolKind::Variable);
+    }
+
+    fn declare_kind(&mut self, name: &Token, kind: SymbolKind) -> usize {
+        self.check_declaration(name);
+        let symbol = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: name.clone(),
+            kind,
+            params: vec![],
+            arity: Arity::exact(0),
+            container: self.current_function,
+        });
+
         if self.scopes_is_empty() {
-            return;
+            self.globals.insert(name.lexeme.clone(), symbol);
+            return symbol;
         }
 
         let size = self.scopes.len();
-        self.scopes[size - 1].insert(name.lexeme.clone(), false);
+        let replaced = self.scopes[size - 1].insert(
+            name.lexeme.clone(),
+            Binding {
+                defined: false,
+                used: false,
+                symbol,
+            },
+        );
+        if let Some(binding) = replaced {
+            self.warn_unused(&name.lexeme, binding);
+        }
+        symbol
     }
 
     fn define(&mut self, name: Token) {
@@ -210,6 +433,8 @@ impl Resolver {
         }
 
         let size = self.scopes.len();
-        self.scopes[size - 1].insert(name.lexeme.clone(), true);
+        if let Some(binding) = self.scopes[size - 1].get_mut(&name.lexeme) {
+            binding.defined = true;
+        }
     }
 }
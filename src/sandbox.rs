@@ -0,0 +1,318 @@
+use crate::expr::LiteralValue;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Rough cost of creating an environment (a block scope or a call frame),
+/// charged against `Config::max_memory`.
+pub const ENVIRONMENT_COST: usize = 64;
+
+/// Rough cost of a number, boolean or `nil`, charged against
+/// `Config::max_memory`.
+pub const VALUE_COST: usize = 16;
+
+/// Call depth allowed when `Config::max_call_depth` is unset but another limit
+/// is, so a sandboxed script can't overflow the host's stack by recursing. It
+/// fits in a 2 MB thread stack even in debug builds; raise it only for hosts
+/// that run scripts on a bigger stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+/// Limits and capabilities for running untrusted scripts. The default
+/// configuration has no limits and allows every native except file I/O.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Maximum number of statements executed per run.
+    pub max_steps: Option<u64>,
+    /// Wall-clock time allowed per run.
+    pub timeout: Option<Duration>,
+    /// Maximum estimated bytes allocated per run. This is an estimate based on
+    /// the values stored and the scopes created, not the real heap usage.
+    pub max_memory: Option<usize>,
+    /// Maximum length of any string a script builds.
+    pub max_string_length: Option<usize>,
+    /// Maximum number of nested function calls. Defaults to
+    /// `DEFAULT_MAX_CALL_DEPTH` when any other limit is set.
+    pub max_call_depth: Option<usize>,
+    pub capabilities: Capabilities,
+}
+
+/// Which groups of natives scripts may use. By default scripts may read the
+/// clock but not touch the file system; hosts that trust their scripts, like
+/// the `bolt` command, opt in with `Capabilities::all()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    /// `clock()`
+    pub clock: bool,
+    /// `read_file(path)` and `write_file(path, contents)`
+    pub file_io: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            clock: true,
+            file_io: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            clock: false,
+            file_io: false,
+        }
+    }
+}
+
+impl Config {
+    /// The call depth limit in effect for this configuration.
+    pub fn call_depth_limit(&self) -> Option<usize> {
+        let limited = self.max_steps.is_some()
+            || self.timeout.is_some()
+            || self.max_memory.is_some()
+            || self.max_string_length.is_some();
+        match self.max_call_depth {
+            Some(max) => Some(max),
+            None if limited => Some(DEFAULT_MAX_CALL_DEPTH),
+            None => None,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            clock: true,
+            file_io: false,
+        }
+    }
+}
+
+/// The limit that stopped a script.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps,
+    Time,
+    Memory,
+    StringLength,
+    CallDepth,
+}
+
+/// Tracks how much of its `Config` a run has used. Shared by an interpreter
+/// and every closure it creates.
+pub struct Budget {
+    config: Config,
+    steps: Cell<u64>,
+    memory: Cell<usize>,
+    depth: Cell<usize>,
+    started: Cell<Instant>,
+    exceeded: Cell<Option<Limit>>,
+}
+
+impl Budget {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            steps: Cell::new(0),
+            memory: Cell::new(0),
+            depth: Cell::new(0),
+            started: Cell::new(Instant::now()),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Starts a new run with the whole budget available again.
+    pub fn reset(&self) {
+        self.steps.set(0);
+        self.memory.set(0);
+        self.depth.set(0);
+        self.started.set(Instant::now());
+        self.exceeded.set(None);
+    }
+
+    /// The limit that was hit during the current run, if any.
+    pub fn exceeded(&self) -> Option<Limit> {
+        self.exceeded.get()
+    }
+
+    fn fail(&self, limit: Limit, msg: String) -> Result<(), String> {
+        self.exceeded.set(Some(limit));
+        Err(msg)
+    }
+
+    /// Counts one executed statement and checks the step and time limits.
+    pub fn step(&self) -> Result<(), String> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max) = self.config.max_steps {
+            if steps > max {
+                return self.fail(Limit::Steps, format!("Step budget of {} exceeded", max));
+            }
+        }
+        self.check_time()
+    }
+
+    /// Checks the time limit, for natives that loop without running any
+    /// statements.
+    pub fn check_time(&self) -> Result<(), String> {
+        if let Some(timeout) = self.config.timeout {
+            if self.started.get().elapsed() > timeout {
+                return self.fail(Limit::Time, format!("Time limit of {:?} exceeded", timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts entering a function call and checks the call depth limit.
+    /// Every successful `enter_call` must be matched by an `exit_call`.
+    pub fn enter_call(&self) -> Result<(), String> {
+        let depth = self.depth.get() + 1;
+        if let Some(max) = self.config.call_depth_limit() {
+            if depth > max {
+                return self.fail(
+                    Limit::CallDepth,
+                    format!("Call depth limit of {} exceeded", max),
+                );
+            }
+        }
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    pub fn exit_call(&self) {
+        self.depth.set(self.depth.get().saturating_sub(1));
+    }
+
+    /// Charges `bytes` against the memory estimate.
+    pub fn allocate(&self, bytes: usize) -> Result<(), String> {
+        let memory = self.memory.get().saturating_add(bytes);
+        self.memory.set(memory);
+        match self.config.max_memory {
+            Some(max) if memory > max => self.fail(
+                Limit::Memory,
+                format!("Memory limit of {} bytes exceeded", max),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with the memory limit if `bytes` more would exceed it, without
+    /// charging them. Natives use this before building a value that is
+    /// charged with `check_value` once they return it.
+    pub fn ensure_available(&self, bytes: usize) -> Result<(), String> {
+        match self.config.max_memory {
+            Some(max) if self.memory.get().saturating_add(bytes) > max => self.fail(
+                Limit::Memory,
+                format!("Memory limit of {} bytes exceeded", max),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a value a script is about to store or use against the string
+    /// length and memory limits.
+    pub fn check_value(&self, value: &LiteralValue) -> Result<(), String> {
+        if let (LiteralValue::StringValue(s), Some(max)) = (value, self.config.max_string_length) {
+            let length = s.chars().count();
+            if length > max {
+                return self.fail(
+                    Limit::StringLength,
+                    format!("String of length {} exceeds the limit of {}", length, max),
+                );
+            }
+        }
+        self.allocate(size_estimate(value))
+    }
+}
+
+/// Estimated bytes that turning the ranges in `value` into arrays would add,
+/// as natives taking an `Array` do.
+pub fn range_expansion_cost(value: &LiteralValue) -> usize {
+    match value {
+        LiteralValue::Range { .. } => (value.range_len() as usize).saturating_mul(VALUE_COST),
+        LiteralValue::Array(items) => items.iter().fold(0, |total, item| {
+            total.saturating_add(range_expansion_cost(item))
+        }),
+        LiteralValue::Object(members) => members.iter().fold(0, |total, (_, item)| {
+            total.saturating_add(range_expansion_cost(item))
+        }),
+        _ => 0,
+    }
+}
+
+fn size_estimate(value: &LiteralValue) -> usize {
+    match value {
+        LiteralValue::StringValue(s) => 24 + s.len(),
+        LiteralValue::Callable { .. } => ENVIRONMENT_COST,
+        LiteralValue::Array(items) => 24 + items.iter().map(size_estimate).sum::<usize>(),
+        LiteralValue::Object(members) => {
+            24 + members
+                .iter()
+                .map(|(key, value)| 24 + key.len() + size_estimate(value))
+                .sum::<usize>()
+        }
+        _ => VALUE_COST,
+    }
+}
+
+/// Lets another thread stop a running interpreter. The interpreter checks
+/// the token at every loop iteration and function call, and fails with an
+/// "Interrupted at line N" error once it has been cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears a cancellation so the interpreter can run again.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with the interrupted error if the token has been cancelled.
+    pub fn check(&self, line: usize) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(format!("Interrupted at line {}", line))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_which_limit_was_hit() {
+        let budget = Budget::new(Config {
+            max_steps: Some(2),
+            ..Config::default()
+        });
+
+        assert!(budget.step().is_ok());
+        assert!(budget.step().is_ok());
+        assert_eq!(budget.step(), Err("Step budget of 2 exceeded".to_string()));
+        assert_eq!(budget.exceeded(), Some(Limit::Steps));
+
+        budget.reset();
+        assert_eq!(budget.exceeded(), None);
+        assert!(budget.step().is_ok());
+    }
+}
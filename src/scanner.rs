@@ -2,12 +2,11 @@ use std::fmt::{self, format};
 use std::collections::HashMap;
 
 fn is_digit(ch: char) -> bool {
-    ch as u8 >= '0' as u8 && ch as u8 <= '9' as u8
+    ch.is_ascii_digit()
 }
 
 fn is_alpha(ch: char) -> bool {
-    let uch = ch as u8;
-    (uch >= 'a' as u8 && uch <= 'z' as u8) || (uch >= 'A' as u8 && uch <= 'Z' as u8) || (ch == '_')
+    ch.is_ascii_alphabetic() || (ch == '_')
 }
 
 fn is_alpha_numeric(ch: char) -> bool {
@@ -23,6 +22,7 @@ fn get_keywords_hashmap() -> HashMap<&'static str, TokenType> {
         ("for", TokenType::For),
         ("fun", TokenType::Fun),
         ("if", TokenType::If),
+        ("in", TokenType::In),
         ("nil", TokenType::Nil),
         ("or", TokenType::Or),
         ("print", TokenType::Print),
@@ -42,6 +42,8 @@ pub struct Scanner {
     start: usize,
     current: usize, 
     line: usize,
+    line_start: usize,
+    start_column: usize,
     keywords: HashMap<&'static str, TokenType>,
 
 }
@@ -54,14 +56,17 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 0,
             keywords: get_keywords_hashmap(),
         }
     }
 
-    pub fn scan_tokens(self: &mut Self) -> Result<Vec<Token>, String> {
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, String> {
         let mut errors = vec![];
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_column = self.start - self.line_start;
             match self.scan_token(){
                 Ok(_) => (),
                 Err(msg) => errors.push(msg),
@@ -73,13 +78,14 @@ impl Scanner {
             lexeme: "".to_string(),
             literal: None,
             lineNumber: self.line,
+            column: self.current - self.line_start,
         });
 
-        if errors.len() > 0 {
+        if !errors.is_empty() {
             let mut joined  = "".to_string();
             for error in errors {
                 joined.push_str(&error);
-                joined.push_str("\n");
+                joined.push('\n');
             };
             return Err(joined);
         }   
@@ -87,11 +93,11 @@ impl Scanner {
         Ok(self.tokens.clone())
     }
 
-    fn is_at_end(self: &Self) -> bool {
+    fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
-    fn scan_token(self: &mut Self) -> Result<(), String>  {
+    fn scan_token(&mut self) -> Result<(), String>  {
         let c = self.advance();
         match c {
             '(' => self.add_token(TokenType::LeftParen),
@@ -99,10 +105,26 @@ impl Scanner {
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
+            '.' => {
+                let token = if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    TokenType::DotDotDot
+                } else if self.char_match('.') {
+                    if self.char_match('=') {
+                        TokenType::DotDotEqual
+                    } else {
+                        TokenType::DotDot
+                    }
+                } else {
+                    TokenType::Dot
+                };
+                self.add_token(token);
+            },
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
+            ':' => self.add_token(TokenType::Colon),
             '*' => self.add_token(TokenType::Star),
             '!' => {
                 let token = if self.char_match('='){
@@ -115,6 +137,8 @@ impl Scanner {
             '=' => {
                 let token = if self.char_match('='){
                     TokenType::EqualEqual
+                } else if self.char_match('>') {
+                    TokenType::Arrow
                 } else {
                     TokenType::Equal
                 };
@@ -124,7 +148,7 @@ impl Scanner {
                 let token = if self.char_match('='){
                     TokenType::LessEqual
                 } else {
-                    TokenType::Equal
+                    TokenType::Less
                 };
                 self.add_token(token);
             },
@@ -132,7 +156,7 @@ impl Scanner {
                 let token = if self.char_match('='){
                     TokenType::GreaterEqual
                 } else {
-                    TokenType::Equal
+                    TokenType::Greater
                 };
                 self.add_token(token);
             },
@@ -149,7 +173,7 @@ impl Scanner {
                 }
             },
             ' ' | '\r' | '\t' => {},
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
             '"' => self.string()?,
             
             c => {
@@ -158,13 +182,18 @@ impl Scanner {
                 } else if is_alpha(c) {
                     self.identifier();
                 } else {
-                    return Err(format!("Unrecognized char: {}", c));
+                    return Err(format!("Line {}: Unrecognized char: {}", self.line, c));
                 }
             }
         }
         Ok(())
     }
 
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn identifier(&mut self) {
         while is_alpha_numeric(self.peek()) {
             self.advance();
@@ -178,7 +207,7 @@ impl Scanner {
         }
     }
 
-    fn number(self: &mut Self) -> Result<(), String> {
+    fn number(&mut self) -> Result<(), String> {
         while is_digit(self.peek()) {
             self.advance();
         }
@@ -194,13 +223,13 @@ impl Scanner {
         let value = substring.parse::<f64>();
         match value {
             Ok(value) =>         self.add_token_lit(TokenType::Number, Some(FValue(value))),
-            Err(value) => return Err(format!( "Could not parse number: {}", substring)),
+            Err(value) => return Err(format!("Line {}: Could not parse number: {}", self.line, substring)),
         }
 
         Ok(())
     }
 
-    fn peek_next(self: &Self) -> char {
+    fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\0';
         }
@@ -208,15 +237,15 @@ impl Scanner {
         self.source.chars().nth(self.current + 1).unwrap()
     }
 
-    fn string(self: &mut Self) -> Result<(), String> {
+    fn string(&mut self) -> Result<(), String> {
         while self.peek() != '"' && !self.is_at_end(){
-            if self.peek() == '\n' {
-                self.line += 1;
+            let c = self.advance();
+            if c == '\n' {
+                self.new_line();
             }
-            self.advance();
         }
         if self.is_at_end(){
-            return  Err("Unterminated string".to_string());
+            return  Err(format!("Line {}: Unterminated string", self.line));
         }
         self.advance();
 
@@ -227,37 +256,37 @@ impl Scanner {
         Ok(())
     }
 
-    fn peek(self: &mut Self) -> char {
+    fn peek(&mut self) -> char {
         if self.is_at_end() {
             return '\0';
         }
         self.source.chars().nth(self.current).unwrap()
     }
 
-    fn char_match(self: &mut Self, ch: char) -> bool {
+    fn char_match(&mut self, ch: char) -> bool {
         if self.is_at_end(){
             return false;
         }
         if self.source.chars().nth(self.current).unwrap() != ch {
-            return false;
+            false
         } else {
             self.current +=1;
-            return true;
+            true
         }
     }
 
-    fn advance(self: &mut Self) -> char {
+    fn advance(&mut self) -> char {
         let c = self.source.chars().nth(self.current).unwrap();
         self.current += 1;
 
-        return c;
+        c
     }
 
-    fn add_token(self: &mut Self, token_type: TokenType) {
+    fn add_token(&mut self, token_type: TokenType) {
         self.add_token_lit(token_type, None);
     }
 
-    fn add_token_lit(self: &mut Self, token_type: TokenType, literal: Option<LiteralValue>) {
+    fn add_token_lit(&mut self, token_type: TokenType, literal: Option<LiteralValue>) {
         // let mut text = "".to_string();
         // let _lit = &self.source[self.start..self.current]
         //     .chars()
@@ -266,10 +295,11 @@ impl Scanner {
         let text = self.source[self.start..self.current].to_string();
 
         self.tokens.push(Token {
-            token_type: token_type,
+            token_type,
             lexeme: text,
-            literal: literal,
+            literal,
             lineNumber: self.line,
+            column: self.start_column,
         });
     }
 }
@@ -286,6 +316,7 @@ pub enum TokenType {
     Minus,
     Plus,
     Semicolon,
+    Colon,
     Slash,
     Star,
 
@@ -298,6 +329,10 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    DotDot,
+    DotDotEqual,
+    DotDotDot,
+    Arrow,
 
     // Literals
     Identifier,
@@ -312,6 +347,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -335,6 +371,63 @@ impl std::fmt::Display for TokenType {
     }
 }
 
+/// Parses the names produced by `Display`, e.g. `"LeftParen"`.
+impl std::str::FromStr for TokenType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "LeftParen" => Ok(TokenType::LeftParen),
+            "RightParen" => Ok(TokenType::RightParen),
+            "LeftBrace" => Ok(TokenType::LeftBrace),
+            "RightBrace" => Ok(TokenType::RightBrace),
+            "Comma" => Ok(TokenType::Comma),
+            "Dot" => Ok(TokenType::Dot),
+            "Minus" => Ok(TokenType::Minus),
+            "Plus" => Ok(TokenType::Plus),
+            "Semicolon" => Ok(TokenType::Semicolon),
+            "Colon" => Ok(TokenType::Colon),
+            "Slash" => Ok(TokenType::Slash),
+            "Star" => Ok(TokenType::Star),
+            "Bang" => Ok(TokenType::Bang),
+            "BangEqual" => Ok(TokenType::BangEqual),
+            "Equal" => Ok(TokenType::Equal),
+            "EqualEqual" => Ok(TokenType::EqualEqual),
+            "Greater" => Ok(TokenType::Greater),
+            "GreaterEqual" => Ok(TokenType::GreaterEqual),
+            "Less" => Ok(TokenType::Less),
+            "LessEqual" => Ok(TokenType::LessEqual),
+            "DotDot" => Ok(TokenType::DotDot),
+            "DotDotEqual" => Ok(TokenType::DotDotEqual),
+            "DotDotDot" => Ok(TokenType::DotDotDot),
+            "Arrow" => Ok(TokenType::Arrow),
+            "Identifier" => Ok(TokenType::Identifier),
+            "StringLit" => Ok(TokenType::StringLit),
+            "Number" => Ok(TokenType::Number),
+            "And" => Ok(TokenType::And),
+            "Class" => Ok(TokenType::Class),
+            "Else" => Ok(TokenType::Else),
+            "False" => Ok(TokenType::False),
+            "Fun" => Ok(TokenType::Fun),
+            "For" => Ok(TokenType::For),
+            "If" => Ok(TokenType::If),
+            "In" => Ok(TokenType::In),
+            "Nil" => Ok(TokenType::Nil),
+            "Or" => Ok(TokenType::Or),
+            "Print" => Ok(TokenType::Print),
+            "Return" => Ok(TokenType::Return),
+            "Super" => Ok(TokenType::Super),
+            "This" => Ok(TokenType::This),
+            "True" => Ok(TokenType::True),
+            "Var" => Ok(TokenType::Var),
+            "While" => Ok(TokenType::While),
+            "Eof" => Ok(TokenType::Eof),
+            "None" => Ok(TokenType::None),
+            other => Err(format!("Unknown token type '{}'", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum LiteralValue {
     FValue(f64),
@@ -348,6 +441,9 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<LiteralValue>,
     pub lineNumber: usize,
+    /// Offset of the token's first character from the start of the line it
+    /// begins on.
+    pub column: usize,
 }
 
 impl Token {
@@ -356,16 +452,18 @@ impl Token {
         lexeme: String,
         literal: Option<LiteralValue>,
         lineNumber: usize,
+        column: usize,
     ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
             lineNumber,
+            column,
         }
     }
 
-    pub fn to_string(self: &Self) -> String {
+    pub fn to_string(&self) -> String {
         format!("{} {} {:?}", self.token_type, self.lexeme, self.literal)
     }
 }
@@ -452,7 +550,7 @@ mod tests {
     fn number_literals() {
         let source = "123.123\n321.0\n5";
         let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
+        scanner.scan_tokens().unwrap();
         for token in &scanner.tokens {
             println!("{:?}", token.token_type);
         }
@@ -483,7 +581,7 @@ mod tests {
     fn get_identifier() {
         let source = "var this_is_a_var = 12;\nwhile true {print 3};";
         let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
+        scanner.scan_tokens().unwrap();
 
         assert_eq!(scanner.tokens.len(), 13);
         // println!("{:?}", scanner.tokens);
@@ -0,0 +1,400 @@
+use crate::ast;
+use crate::environment::Environment;
+use crate::expr::{Expr, LiteralValue};
+use crate::interpreter::Interpreter;
+use crate::json::Json;
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Version of the layout written by [`to_json`].
+pub const VERSION: usize = 1;
+
+/// Saves the global environment of `interpreter` to `path`. Returns a
+/// message for every value that couldn't be saved.
+pub fn save(interpreter: &Interpreter, path: &Path) -> Result<Vec<String>, String> {
+    let (json, skipped) = to_json(interpreter);
+    fs::write(path, json.to_string()).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(skipped)
+}
+
+/// Loads a snapshot written by [`save`] into the global environment of
+/// `interpreter`. Returns a message for every value that couldn't be
+/// restored.
+pub fn restore(interpreter: &mut Interpreter, path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let json = Json::parse(&text).map_err(|e| e.to_string())?;
+    from_json(interpreter, &json)
+}
+
+/// Serializes the global environment and every environment a saved closure
+/// captures. Environments are stored in a list, globals first, and refer to
+/// each other by index, so closures that share state still share it after a
+/// restore. Functions are saved as their declaration's syntax tree (see
+/// [`ast`]). The natives every interpreter registers are left out; other
+/// natives are saved by name.
+pub fn to_json(interpreter: &Interpreter) -> (Json, Vec<String>) {
+    let mut writer = Writer {
+        environments: vec![],
+        indices: HashMap::new(),
+        skipped: vec![],
+    };
+    writer.environment(&interpreter.environment, None);
+    let json = Json::object(vec![
+        ("version", VERSION.into()),
+        ("environments", writer.environments.into()),
+    ]);
+    (json, writer.skipped)
+}
+
+/// Restores a snapshot serialized by [`to_json`].
+pub fn from_json(interpreter: &mut Interpreter, json: &Json) -> Result<Vec<String>, String> {
+    match json.get("version").and_then(Json::as_usize) {
+        Some(VERSION) => {}
+        Some(version) => return Err(format!("Unsupported snapshot version {}", version)),
+        None => return Err("Snapshot is missing 'version'".to_string()),
+    }
+    let saved = json
+        .get("environments")
+        .and_then(Json::as_array)
+        .filter(|environments| !environments.is_empty())
+        .ok_or("Snapshot has no environments")?;
+
+    let environments: Vec<Rc<RefCell<Environment>>> = (0..saved.len())
+        .map(|index| match index {
+            0 => interpreter.environment.clone(),
+            _ => Rc::new(RefCell::new(Environment::new())),
+        })
+        .collect();
+    for (saved, environment) in saved.iter().zip(&environments).skip(1) {
+        match saved.get("enclosing") {
+            None | Some(Json::Null) => {}
+            Some(index) => {
+                environment.borrow_mut().enclosing = Some(environment_at(&environments, index)?)
+            }
+        }
+    }
+
+    let reader = Reader {
+        interpreter: interpreter.clone(),
+        environments,
+    };
+    let mut skipped = vec![];
+    for (saved, environment) in saved.iter().zip(&reader.environments) {
+        let Some(Json::Object(values)) = saved.get("values") else {
+            return Err("Snapshot environment is missing 'values'".to_string());
+        };
+        for (name, value) in values {
+            match reader.value(value)? {
+                Ok(value) => environment.borrow_mut().define(name.clone(), value),
+                Err(reason) => skipped.push(format!("{}: {}", name, reason)),
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+fn environment_at(
+    environments: &[Rc<RefCell<Environment>>],
+    index: &Json,
+) -> Result<Rc<RefCell<Environment>>, String> {
+    index
+        .as_usize()
+        .and_then(|index| environments.get(index))
+        .cloned()
+        .ok_or_else(|| format!("Invalid environment index {}", index.to_string()))
+}
+
+/// A name for the function `name` in messages about the values it captures.
+fn function_label(name: &str, declaration: &Stmt) -> String {
+    match declaration {
+        Stmt::Function { .. } => name.to_string(),
+        _ => format!("<anon line {}>", declaration.line().unwrap_or(0)),
+    }
+}
+
+struct Writer {
+    environments: Vec<Json>,
+    indices: HashMap<*const RefCell<Environment>, usize>,
+    skipped: Vec<String>,
+}
+
+impl Writer {
+    /// Saves `environment` and the environments enclosing it, unless that was
+    /// already done, and returns its index. `owner` names the function that
+    /// captured it, for messages.
+    fn environment(
+        &mut self,
+        environment: &Rc<RefCell<Environment>>,
+        owner: Option<&str>,
+    ) -> usize {
+        if let Some(index) = self.indices.get(&Rc::as_ptr(environment)) {
+            return *index;
+        }
+        let index = self.environments.len();
+        self.environments.push(Json::Null);
+        self.indices.insert(Rc::as_ptr(environment), index);
+
+        let (enclosing, values) = {
+            let environment = environment.borrow();
+            (environment.enclosing.clone(), environment.values())
+        };
+        let enclosing = match enclosing {
+            Some(enclosing) => self.environment(&enclosing, owner).into(),
+            None => Json::Null,
+        };
+        let mut saved = vec![];
+        for (name, value) in values {
+            let registered = matches!(
+                &value,
+                LiteralValue::Callable { name: native, source: None, .. } if *native == name
+            );
+            if index == 0 && registered {
+                continue;
+            }
+            match self.value(&value) {
+                Ok(json) => saved.push((name, json)),
+                Err(reason) => self.skipped.push(match owner {
+                    Some(owner) => format!("{} (captured by {}): {}", name, owner, reason),
+                    None => format!("{}: {}", name, reason),
+                }),
+            }
+        }
+        self.environments[index] = Json::object(vec![
+            ("enclosing", enclosing),
+            ("values", Json::Object(saved)),
+        ]);
+        index
+    }
+
+    fn value(&mut self, value: &LiteralValue) -> Result<Json, String> {
+        Ok(match value {
+            LiteralValue::Nil => Json::Null,
+            LiteralValue::True => Json::Bool(true),
+            LiteralValue::False => Json::Bool(false),
+            LiteralValue::Number(x) if x.is_finite() => Json::Number(*x),
+            LiteralValue::Number(x) => return Err(format!("the number {} can't be saved", x)),
+            LiteralValue::StringValue(s) => s.clone().into(),
+            LiteralValue::Array(items) => Json::object(vec![(
+                "array",
+                items
+                    .iter()
+                    .map(|item| self.value(item))
+                    .collect::<Result<Vec<Json>, String>>()?
+                    .into(),
+            )]),
+            LiteralValue::Object(members) => Json::object(vec![(
+                "object",
+                Json::Object(
+                    members
+                        .iter()
+                        .map(|(key, value)| Ok((key.clone(), self.value(value)?)))
+                        .collect::<Result<Vec<(String, Json)>, String>>()?,
+                ),
+            )]),
+            LiteralValue::Range {
+                start,
+                end,
+                inclusive,
+            } => Json::object(vec![(
+                "range",
+                Json::object(vec![
+                    ("start", (*start).into()),
+                    ("end", (*end).into()),
+                    ("inclusive", (*inclusive).into()),
+                ]),
+            )]),
+            LiteralValue::Callable {
+                name,
+                source: Some(source),
+                ..
+            } => {
+                let owner = function_label(name, &source.declaration);
+                let closure = self.environment(&source.closure, Some(&owner));
+                Json::object(vec![(
+                    "function",
+                    Json::object(vec![
+                        ("declaration", ast::stmt_to_json(&source.declaration)),
+                        ("closure", closure.into()),
+                    ]),
+                )])
+            }
+            LiteralValue::Callable { name, .. } => {
+                Json::object(vec![("native", name.clone().into())])
+            }
+        })
+    }
+}
+
+struct Reader {
+    interpreter: Interpreter,
+    environments: Vec<Rc<RefCell<Environment>>>,
+}
+
+impl Reader {
+    /// Rebuilds a saved value. The outer error means the snapshot is
+    /// malformed; the inner one that this value can't be restored here.
+    fn value(&self, json: &Json) -> Result<Result<LiteralValue, String>, String> {
+        Ok(Ok(match json {
+            Json::Null => LiteralValue::Nil,
+            Json::Bool(b) => LiteralValue::from_bool(*b),
+            Json::Number(x) => LiteralValue::Number(*x),
+            Json::String(s) => LiteralValue::StringValue(s.clone()),
+            Json::Object(members) => match members.as_slice() {
+                [(kind, Json::Array(items))] if kind == "array" => {
+                    let mut values = vec![];
+                    for item in items {
+                        match self.value(item)? {
+                            Ok(value) => values.push(value),
+                            Err(reason) => return Ok(Err(reason)),
+                        }
+                    }
+                    LiteralValue::Array(Rc::new(values))
+                }
+                [(kind, Json::Object(members))] if kind == "object" => {
+                    let mut values = vec![];
+                    for (key, member) in members {
+                        match self.value(member)? {
+                            Ok(value) => values.push((key.clone(), value)),
+                            Err(reason) => return Ok(Err(reason)),
+                        }
+                    }
+                    LiteralValue::Object(Rc::new(values))
+                }
+                [(kind, range)] if kind == "range" => {
+                    match (
+                        range.get("start").and_then(Json::as_f64),
+                        range.get("end").and_then(Json::as_f64),
+                        range.get("inclusive").and_then(Json::as_bool),
+                    ) {
+                        (Some(start), Some(end), Some(inclusive)) => LiteralValue::Range {
+                            start,
+                            end,
+                            inclusive,
+                        },
+                        _ => return Err(format!("Invalid snapshot range {}", range.to_string())),
+                    }
+                }
+                [(kind, function)] if kind == "function" => self.function(function)?,
+                [(kind, Json::String(name))] if kind == "native" => {
+                    match self.interpreter.get_global(name) {
+                        Some(native @ LiteralValue::Callable { source: None, .. }) => native,
+                        _ => {
+                            return Ok(Err(format!("native function '{}' is not available", name)))
+                        }
+                    }
+                }
+                _ => return Err(format!("Invalid snapshot value {}", json.to_string())),
+            },
+            Json::Array(_) => return Err(format!("Invalid snapshot value {}", json.to_string())),
+        }))
+    }
+
+    fn function(&self, json: &Json) -> Result<LiteralValue, String> {
+        let declaration = ast::stmt_from_json(
+            json.get("declaration")
+                .ok_or("Snapshot function is missing 'declaration'")?,
+        )?;
+        let closure = json
+            .get("closure")
+            .ok_or("Snapshot function is missing 'closure'")?;
+
+        let mut host = self.interpreter.clone();
+        host.environment = environment_at(&self.environments, closure)?;
+        match &declaration {
+            Stmt::Function { .. } => host.function_value(&declaration),
+            Stmt::Expression {
+                expression: expression @ Expr::AnonFunction { .. },
+            } => expression.evaluate(&host),
+            _ => Err("Snapshot function is not a function declaration".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run;
+
+    fn restored(source: &str) -> (Interpreter, Vec<String>, Vec<String>) {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, source).unwrap();
+        let (json, skipped) = to_json(&interpreter);
+
+        let text = json.to_string();
+        let mut fresh = Interpreter::new();
+        let missing = from_json(&mut fresh, &Json::parse(&text).unwrap()).unwrap();
+        (fresh, skipped, missing)
+    }
+
+    #[test]
+    fn restores_data_and_functions() {
+        let source = "var n = 1.5;\nvar s = \"text\";\nvar items = json_parse(\"[1, true, null]\");\nfun add(a, b) { return a + b; }\nvar double = fun (x) { return add(x, x); };\nvar r = 0..=2;\n";
+        let (fresh, skipped, missing) = restored(source);
+
+        assert!(skipped.is_empty() && missing.is_empty());
+        assert_eq!(fresh.get_global("n"), Some(LiteralValue::Number(1.5)));
+        assert_eq!(
+            fresh.get_global("s"),
+            Some(LiteralValue::StringValue("text".into()))
+        );
+        assert_eq!(
+            fresh.get_global("items").unwrap().to_string(),
+            "[1, true, nil]"
+        );
+        assert_eq!(fresh.get_global("r").unwrap().to_string(), "0..=2");
+        assert_eq!(
+            fresh.call_fn("double", vec![LiteralValue::Number(4.0)]),
+            Ok(LiteralValue::Number(8.0))
+        );
+    }
+
+    #[test]
+    fn closures_keep_their_shared_state() {
+        let source = "var inc = nil;\nvar peek = nil;\nfun counter() {\n  var count = 0;\n  inc = fun () { count = count + 1; return count; };\n  peek = fun () { return count; };\n}\ncounter();\ninc();\ninc();\n";
+        let (fresh, _, _) = restored(source);
+
+        assert_eq!(fresh.call_fn("inc", vec![]), Ok(LiteralValue::Number(3.0)));
+        assert_eq!(fresh.call_fn("peek", vec![]), Ok(LiteralValue::Number(3.0)));
+    }
+
+    #[test]
+    fn reports_values_that_cannot_be_saved() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("host", || Ok(1.0));
+        run(
+            &mut interpreter,
+            "var bad = 0 / 0;\nvar h = host;\nvar c = clock;\nfun f() { var inf = -1 / 0; fun g() { return inf; } return g; }\nvar g = f();\n",
+        )
+        .unwrap();
+        let (json, skipped) = to_json(&interpreter);
+        assert_eq!(
+            skipped,
+            vec![
+                "bad: the number NaN can't be saved".to_string(),
+                "inf (captured by g): the number -inf can't be saved".to_string(),
+            ]
+        );
+
+        let mut fresh = Interpreter::new();
+        let missing = from_json(&mut fresh, &json).unwrap();
+        assert_eq!(
+            missing,
+            vec!["h: native function 'host' is not available".to_string()]
+        );
+        assert!(fresh.call_fn("c", vec![]).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut interpreter = Interpreter::new();
+        let json = Json::parse("{\"version\": 99, \"environments\": []}").unwrap();
+        assert_eq!(
+            from_json(&mut interpreter, &json),
+            Err("Unsupported snapshot version 99".to_string())
+        );
+    }
+}
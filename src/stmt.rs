@@ -4,21 +4,46 @@ use crate::scanner::Token;
 #[derive(Clone)]
 pub enum Stmt {
     Expression { expression: Expr },
-    Print { expression: Expr },
-    Var { name: Token, initializer: Expr },
+    Print {
+        keyword: Token,
+        expression: Expr,
+    },
+    Var {
+        name: Token,
+        /// Declared type name from `var x: Type`, if any.
+        type_annotation: Option<Token>,
+        initializer: Expr,
+    },
     Block { statements: Vec<Box<Stmt>> },
     IfStmt {
+        keyword: Token,
         predicate: Expr,
         then: Box<Stmt>,
         els: Option<Box<Stmt>>,
     },
-        WhileStmt {
+    WhileStmt {
+        /// The `while` token, or `for` when desugared from a for loop.
+        keyword: Token,
         condition: Expr,
         body: Box<Stmt>,
     },
+    /// `for (variable in iterable) body`.
+    ForIn {
+        keyword: Token,
+        variable: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
     Function {
         name: Token,
         params: Vec<Token>,
+        /// Declared type of each parameter, parallel to `params`.
+        param_types: Vec<Option<Token>>,
+        /// Default value of each parameter, parallel to `params`.
+        defaults: Vec<Option<Expr>>,
+        /// Whether the last parameter is `...rest`.
+        rest: bool,
+        return_type: Option<Token>,
         body: Vec<Box<Stmt>>,
     },
     ReturnStmt {
@@ -36,27 +61,111 @@ pub enum Stmt {
     // }
 }
 
+/// A `test "name" { ... }` block collected by the parser for `bolt test`.
+#[derive(Clone)]
+pub struct TestDecl {
+    pub keyword: Token,
+    pub name: String,
+    pub body: Vec<Box<Stmt>>,
+}
+
 impl Stmt {
+    /// Source line the statement starts on. Blocks take the line of their
+    /// first statement; expression statements made of a bare literal have
+    /// none.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Expression { expression } => expression.line(),
+            Stmt::Print { keyword, .. }
+            | Stmt::IfStmt { keyword, .. }
+            | Stmt::WhileStmt { keyword, .. }
+            | Stmt::ForIn { keyword, .. }
+            | Stmt::ReturnStmt { keyword, .. } => Some(keyword.lineNumber),
+            Stmt::Var { name, .. } | Stmt::Function { name, .. } => Some(name.lineNumber),
+            Stmt::Block { statements } => statements.first().and_then(|stmt| stmt.line()),
+        }
+    }
+
     pub fn to_string(&self) -> String {
         // use Stmt::*;
         match self {
             Stmt::Expression { expression } => expression.to_string(),
-            Stmt::Print { expression } => format!("(print {})", expression.to_string()),
-            Stmt::Var { name, initializer: _ } => format!("(var {})", name.to_string()),
+            Stmt::Print { expression, .. } => format!("(print {})", expression.to_string()),
+            Stmt::Var {
+                name, initializer, ..
+            } => format!("(var {} {})", name.lexeme, initializer.to_string()),
             Stmt::Block { statements } => {
-                format!(
-                    "(block {})",
-                    statements
-                        .into_iter()
-                        .map(|stmt| stmt.to_string())
-                        .collect::<String>()
-                )
+                format!("(block {})", join(statements))
             }
-            Stmt::IfStmt { predicate: _, then: _, els: _ } => todo!(),
-            Stmt::WhileStmt { condition: _, body: _ } => todo!(),
-            Stmt::Function { name, params, body } => todo!(),
-            Stmt::ReturnStmt { keyword, value } => todo!(),
+            Stmt::IfStmt {
+                predicate,
+                then,
+                els,
+                ..
+            } => match els {
+                Some(els) => format!(
+                    "(if {} {} {})",
+                    predicate.to_string(),
+                    then.to_string(),
+                    els.to_string()
+                ),
+                None => format!("(if {} {})", predicate.to_string(), then.to_string()),
+            },
+            Stmt::WhileStmt {
+                condition, body, ..
+            } => format!("(while {} {})", condition.to_string(), body.to_string()),
+            Stmt::ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            } => format!(
+                "(for {} {} {})",
+                variable.lexeme,
+                iterable.to_string(),
+                body.to_string()
+            ),
+            Stmt::Function {
+                name,
+                params,
+                defaults,
+                rest,
+                body,
+                ..
+            } => format!(
+                "(fun {} ({}) {})",
+                name.lexeme,
+                params_to_string(params, defaults, *rest),
+                join(body)
+            ),
+            Stmt::ReturnStmt { value, .. } => match value {
+                Some(value) => format!("(return {})", value.to_string()),
+                None => "(return)".to_string(),
+            },
             // Stmt::ForStmt { var_decl, expr_stmt, condition, increment, body } => todo!(),
         }
     }
 }
+
+fn join(stmts: &[Box<Stmt>]) -> String {
+    stmts
+        .iter()
+        .map(|stmt| stmt.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Renders a parameter list as `a b=2 ...rest`.
+pub(crate) fn params_to_string(params: &[Token], defaults: &[Option<Expr>], rest: bool) -> String {
+    params
+        .iter()
+        .zip(defaults)
+        .enumerate()
+        .map(|(i, (param, default))| match default {
+            Some(default) => format!("{}={}", param.lexeme, default.to_string()),
+            None if rest && i == params.len() - 1 => format!("...{}", param.lexeme),
+            None => param.lexeme.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -0,0 +1,117 @@
+use crate::debugger::DebugHook;
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::{Stmt, TestDecl};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The result of running one `test "name" { ... }` block.
+pub struct TestOutcome {
+    pub name: String,
+    pub line: usize,
+    pub duration: Duration,
+    pub output: String,
+    pub error: Option<String>,
+    /// Line of the statement that failed, such as the failing `assert`.
+    pub failed_line: Option<usize>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Parses `source`, returning the top-level program and its test blocks
+/// separately.
+pub fn parse_tests(source: &str) -> Result<(Vec<Stmt>, Vec<TestDecl>), Error> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().map_err(Error::Scan)?;
+
+    let mut parser = Parser::new(tokens);
+    let stmts = parser.parse().map_err(Error::Parse)?;
+    Ok((stmts, parser.tests().to_vec()))
+}
+
+/// Runs every test in its own interpreter. The top-level program is run
+/// first in each one, so tests see the script's functions and globals but
+/// never each other's changes.
+pub fn run_tests(program: &[Stmt], tests: &[TestDecl]) -> Vec<TestOutcome> {
+    tests.iter().map(|test| run_test(program, test)).collect()
+}
+
+/// Remembers the line of the last statement started, which is the failing
+/// one once the interpreter returns an error.
+#[derive(Default)]
+struct LastLine(Option<usize>);
+
+impl DebugHook for LastLine {
+    fn before_stmt(&mut self, stmt: &Stmt, _: &Interpreter) -> Result<(), String> {
+        if let Some(line) = stmt.line() {
+            self.0 = Some(line);
+        }
+        Ok(())
+    }
+}
+
+fn run_test(program: &[Stmt], test: &TestDecl) -> TestOutcome {
+    let start = Instant::now();
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+    let last_line = Rc::new(RefCell::new(LastLine::default()));
+    interpreter.set_debug_hook(last_line.clone());
+
+    let body = Stmt::Block {
+        statements: test.body.clone(),
+    };
+    let error = match interpreter.interpret(program.iter().collect()) {
+        Err(msg) => Some(format!("Error while running the script: {}", msg)),
+        Ok(_) => interpreter.interpret(vec![&body]).err(),
+    };
+
+    let output = String::from_utf8_lossy(&output.borrow()).to_string();
+    let failed_line = error.as_ref().and(last_line.borrow().0);
+    TestOutcome {
+        name: test.name.clone(),
+        line: test.keyword.lineNumber,
+        duration: start.elapsed(),
+        failed_line,
+        output,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_tests_in_isolation() {
+        let source = r#"
+            var count = 0;
+            fun bump() { count = count + 1; return count; }
+
+            test "first" { assert_eq(bump(), 1); }
+            test "second" { assert_eq(bump(), 1); }
+            test "failing" {
+                assert(count == 1, "count should be one");
+            }
+        "#;
+        let (program, tests) = parse_tests(source).unwrap();
+        let outcomes = run_tests(&program, &tests);
+
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes[0].passed());
+        assert!(outcomes[1].passed());
+        assert_eq!(outcomes[2].line, 7);
+        assert_eq!(outcomes[2].failed_line, Some(8));
+        assert_eq!(outcomes[0].failed_line, None);
+        assert_eq!(
+            outcomes[2].error,
+            Some("Assertion failed: count should be one".to_string())
+        );
+    }
+}
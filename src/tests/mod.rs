@@ -1,21 +1,26 @@
 #[cfg(test)]
 mod tests {
-    use std::{
-        env,
-        process::{Command, Output}, vec,
-    };
+    use std::{cell::RefCell, fs, path::PathBuf, rc::Rc, vec};
 
-    use crate::run_file;
+    use crate::expr::LiteralValue;
+    use crate::interpreter::Interpreter;
+    use crate::{eval, eval_with, golden, run, run_unoptimized, Capabilities, Config, Error, Limit};
+    use std::time::Duration;
+
+    fn run_case(path: &str) -> String {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+        let contents = fs::read_to_string(path).unwrap();
+        run(&mut interpreter, &contents).unwrap();
+
+        let bytes = output.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
 
     #[test]
     fn interpret_block() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/block.bolt")
-        .output()
-        .unwrap();
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/block.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -26,13 +31,8 @@ mod tests {
 
     #[test]
     fn interpret_while() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/while.bolt")
-        .output()
-        .unwrap();
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/while.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -43,13 +43,8 @@ mod tests {
 
     #[test]
     fn interpret_while_math() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/whilemath.bolt")
-        .output()
-        .unwrap();
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/whilemath.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -68,14 +63,8 @@ mod tests {
 
     #[test]
     fn interpret_for() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/forloop.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/forloop.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -87,7 +76,7 @@ mod tests {
         for _i in 0..21 {
             fibo.push(a);
             temp = b;
-            b = a + b;
+            b += a;
             a = temp;
         }
 
@@ -99,14 +88,8 @@ mod tests {
 
     #[test]
     fn interpret_fun() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/fundef.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/fundef.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -118,14 +101,8 @@ mod tests {
 
     #[test]
     fn interpret_fun_local() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/fundef_local.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/fundef_local.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -135,14 +112,8 @@ mod tests {
 
     #[test]
     fn interpret_fun_return() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funreturn.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funreturn.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -152,14 +123,8 @@ mod tests {
 
     #[test]
     fn interpret_fun_noreturn() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funnoreturn.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funnoreturn.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -171,14 +136,8 @@ mod tests {
 
     #[test]
     fn interpret_fun_condreturn() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funcondreturn.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funcondreturn.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -191,32 +150,20 @@ mod tests {
 
     #[test]
     fn interpret_fun_verynested() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funverynest.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funverynest.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
         assert_eq!(lines.len(), 3, "Output: '{}'", lines.join("\n"));
-        assert_eq!(lines[0], "1");
-        assert_eq!(lines[1], "1");
+        assert_eq!(lines[0], "2");
+        assert_eq!(lines[1], "3");
     }
 
     #[test]
     fn interpret_fun_closure() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funclosure.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funclosure.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -229,14 +176,8 @@ mod tests {
 
     #[test]
     fn interpret_fun_anon() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funanon.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funanon.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
@@ -248,18 +189,324 @@ mod tests {
 
     #[test]
     fn interpret_fun_anon2() {
-        let output = Command::new("cargo")
-        .arg("run")
-        .arg("./src/tests/cases/funanon2.bolt")
-        .output()
-        .unwrap();
-    // println!("in for statement");
-        let lines = std::str::from_utf8(output.stdout.as_slice())
-            .unwrap()
+        let output = run_case("./src/tests/cases/funanon2.bolt");
+        let lines = output
             .split("\n")
             .collect::<Vec<&str>>();
 
         assert_eq!(lines.len(), 2, "Output: '{}'", lines.join("\n"));
         assert_eq!(lines[0], "1");
     }
+
+    #[test]
+    fn native_functions_and_callbacks() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+        interpreter.register_fn("add", |a: f64, b: f64| -> Result<f64, String> { Ok(a + b) });
+
+        run(&mut interpreter, "print add(1, 2); fun twice(x) { return x * 2; }").unwrap();
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "3\n");
+
+        let result = interpreter
+            .call_fn("twice", vec![LiteralValue::Number(21.0)])
+            .unwrap();
+        assert_eq!(result, LiteralValue::Number(42.0));
+
+        let err = run(&mut interpreter, "add(1, \"2\");").unwrap_err();
+        assert_eq!(err.to_string(), "add expected argument 2 to be Number but got String");
+    }
+
+    #[test]
+    fn eval_returns_last_expression() {
+        assert_eq!(eval("var a = 2; a * 21;").unwrap(), LiteralValue::Number(42.0));
+        assert_eq!(eval("var a = 2;").unwrap(), LiteralValue::Nil);
+
+        assert!(matches!(eval("var a = ;"), Err(Error::Parse(_))));
+        assert!(matches!(eval("1 - \"a\";"), Err(Error::Runtime(_))));
+    }
+
+    #[test]
+    fn golden_cases() {
+        let cases = golden::discover(&[PathBuf::from("./src/tests/cases")]).unwrap();
+        assert!(!cases.is_empty());
+
+        for result in golden::run_cases(&cases) {
+            assert!(
+                result.passed(),
+                "{}:\n{}",
+                result.path.display(),
+                result.failures.join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn optimizer_preserves_semantics() {
+        fn output_of(
+            runner: fn(&mut Interpreter, &str) -> Result<(), Error>,
+            source: &str,
+        ) -> (String, Result<(), Error>) {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+            let result = runner(&mut interpreter, source);
+            let bytes = output.borrow().clone();
+            (String::from_utf8(bytes).unwrap(), result)
+        }
+
+        let cases = golden::discover(&[PathBuf::from("./src/tests/cases")]).unwrap();
+        for case in cases {
+            let source = fs::read_to_string(&case).unwrap();
+            assert_eq!(
+                output_of(run, &source),
+                output_of(run_unoptimized, &source),
+                "{}",
+                case.display()
+            );
+        }
+    }
+
+    fn sandboxed(config: Config) -> Interpreter {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        Interpreter::with_config(output.clone(), output, config)
+    }
+
+    fn limit_of(result: Result<(), Error>) -> Option<Limit> {
+        match result {
+            Err(Error::Limit(limit, _)) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn sandbox_limits_stop_runaway_scripts() {
+        let mut steps = sandboxed(Config {
+            max_steps: Some(1000),
+            ..Config::default()
+        });
+        assert_eq!(limit_of(run(&mut steps, "while (true) {}")), Some(Limit::Steps));
+
+        let mut time = sandboxed(Config {
+            timeout: Some(Duration::from_millis(20)),
+            ..Config::default()
+        });
+        assert_eq!(limit_of(run(&mut time, "while (true) {}")), Some(Limit::Time));
+
+        let mut memory = sandboxed(Config {
+            max_memory: Some(10_000),
+            ..Config::default()
+        });
+        let source = "var n = 0; while (true) { var s = \"abc\"; n = n + 1; }";
+        assert_eq!(limit_of(run(&mut memory, source)), Some(Limit::Memory));
+
+        let mut strings = sandboxed(Config {
+            max_string_length: Some(100),
+            ..Config::default()
+        });
+        let source = "var s = \"ab\"; while (true) s = s + s;";
+        assert_eq!(
+            run(&mut strings, source),
+            Err(Error::Limit(
+                Limit::StringLength,
+                "String of length 128 exceeds the limit of 100".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn sandbox_limits_call_depth() {
+        let source = "fun f(n) { return f(n + 1); } f(0);";
+        let mut strict = sandboxed(Config {
+            max_steps: Some(1_000_000),
+            timeout: Some(Duration::from_secs(5)),
+            max_memory: Some(1_000_000),
+            capabilities: Capabilities::none(),
+            ..Config::default()
+        });
+        assert_eq!(
+            run(&mut strict, source),
+            Err(Error::Limit(
+                Limit::CallDepth,
+                "Call depth limit of 100 exceeded".to_string()
+            ))
+        );
+
+        let mut shallow = sandboxed(Config {
+            max_call_depth: Some(10),
+            ..Config::default()
+        });
+        let source = "fun down(n) { if (n == 0) return 0; return down(n - 1); }";
+        assert_eq!(run(&mut shallow, &format!("{} down(9);", source)), Ok(()));
+        assert_eq!(
+            limit_of(run(&mut shallow, &format!("{} down(10);", source))),
+            Some(Limit::CallDepth)
+        );
+    }
+
+    #[test]
+    fn sandbox_limits_ranges_passed_to_natives() {
+        let mut interpreter = sandboxed(Config {
+            max_memory: Some(1_000_000),
+            ..Config::default()
+        });
+        for source in [
+            "map(0..100000000000, x => x);",
+            "json_stringify(0..100000000000, nil);",
+            "json_stringify(map(0..2, x => 0..100000000000), nil);",
+        ] {
+            assert_eq!(limit_of(run(&mut interpreter, source)), Some(Limit::Memory));
+        }
+        assert_eq!(run(&mut interpreter, "print map(0..3, x => x * 2);"), Ok(()));
+    }
+
+    #[test]
+    fn sandbox_budget_starts_over_on_each_run() {
+        let mut interpreter = sandboxed(Config {
+            max_steps: Some(10),
+            ..Config::default()
+        });
+
+        for _ in 0..3 {
+            assert_eq!(run(&mut interpreter, "var a = 1; print a;"), Ok(()));
+        }
+        assert_eq!(
+            run(&mut interpreter, "var a = \"not a number\"; print a - 1;"),
+            Err(Error::Runtime("Minus is not defined string and number".to_string()))
+        );
+    }
+
+    #[test]
+    fn capabilities_disable_natives() {
+        let mut interpreter = sandboxed(Config {
+            capabilities: Capabilities::none(),
+            ..Config::default()
+        });
+
+        assert!(interpreter.get_global("clock").is_none());
+        assert!(interpreter.get_global("read_file").is_none());
+        assert!(interpreter.get_global("assert").is_some());
+        assert!(matches!(run(&mut interpreter, "clock();"), Err(Error::Runtime(_))));
+
+        let interpreter = Interpreter::new();
+        assert!(interpreter.get_global("clock").is_some());
+        assert!(interpreter.get_global("write_file").is_none());
+    }
+
+    #[test]
+    fn cancellation_token_interrupts_from_another_thread() {
+        let mut interpreter = Interpreter::with_output(
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(Vec::new())),
+        );
+        let token = interpreter.cancellation_token();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            token.cancel();
+        });
+
+        let result = run(&mut interpreter, "var i = 0;\nwhile (true) {\n  i = i + 1;\n}");
+        canceller.join().unwrap();
+        assert_eq!(result, Err(Error::Interrupted("Interrupted at line 2".to_string())));
+
+        interpreter.cancellation_token().reset();
+        assert_eq!(run(&mut interpreter, "print i > 0;"), Ok(()));
+    }
+
+    #[test]
+    fn cancellation_is_checked_at_function_calls() {
+        let mut interpreter = Interpreter::with_output(
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(Vec::new())),
+        );
+        let token = interpreter.cancellation_token();
+        interpreter.register_fn("stop", move || -> Result<(), String> {
+            token.cancel();
+            Ok(())
+        });
+
+        let source = "fun f() { return 1; }\nstop();\nprint f();";
+        assert_eq!(
+            run(&mut interpreter, source),
+            Err(Error::Interrupted("Interrupted at line 3".to_string()))
+        );
+    }
+
+    #[test]
+    fn for_in_uses_the_next_member_of_objects() {
+        let source = "var seen = 0;
+var it = set(json_parse(\"{}\"), \"next\", () => { seen = seen + 1; if (seen > 3) return nil; seen; });
+var total = 0;
+for (n in it) total = total + n;
+total;";
+        assert_eq!(eval(source).unwrap(), LiteralValue::Number(6.0));
+
+        let err = eval("for (x in json_parse(\"{}\")) print x;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can't iterate over Object without a 'next' function"
+        );
+        let err = eval("set(json_parse(\"[1]\"), 1, 2);").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "set index 1 is out of bounds for an Array of length 1"
+        );
+    }
+
+    #[test]
+    fn json_natives_read_config_files() {
+        let path = std::env::temp_dir().join("bolt-json-config.json");
+        fs::write(&path, "{\n  \"name\": \"bolt\",\n  \"limits\": {\"steps\": 100}\n}").unwrap();
+        let source = format!(
+            "var config = json_parse(read_file(\"{}\"));\nget(get(config, \"limits\"), \"steps\") + len(get(config, \"name\"));",
+            path.display()
+        );
+        let mut interpreter = sandboxed(Config {
+            capabilities: Capabilities::all(),
+            ..Config::default()
+        });
+        assert_eq!(
+            eval_with(&mut interpreter, &source).unwrap(),
+            LiteralValue::Number(104.0)
+        );
+
+        fs::write(&path, "{\n  \"name\" \"bolt\"\n}").unwrap();
+        let err = eval_with(&mut interpreter, &source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "json_parse: Line 2, column 10: Expected ':' after object key"
+        );
+        fs::remove_file(&path).unwrap();
+
+        let mut strict = sandboxed(Config {
+            max_memory: Some(1_000_000),
+            ..Config::default()
+        });
+        for indent in ["100000000000000000000", "4000000000"] {
+            let source = format!("json_stringify(json_parse(\"[[1]]\"), {});", indent);
+            assert_eq!(
+                run(&mut strict, &source),
+                Err(Error::Runtime(
+                    "json_stringify: indent can't be more than 10".to_string()
+                ))
+            );
+        }
+
+        let err = eval("json_stringify(clock, nil);").unwrap_err();
+        assert_eq!(err.to_string(), "json_stringify: Function 'clock' can't be converted to JSON");
+    }
+
+    #[test]
+    fn json_ast_runs_like_the_source() {
+        let source = fs::read_to_string("./src/tests/cases/funclosure.bolt").unwrap();
+        let json = crate::ast::to_json(&crate::parse(&source).unwrap()).stringify(2);
+
+        let stmts = crate::load_ast(&json).unwrap();
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::with_output(output.clone(), output.clone());
+        interpreter.execute(stmts.iter().collect()).unwrap();
+
+        let from_json = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(from_json, run_case("./src/tests/cases/funclosure.bolt"));
+
+        assert!(matches!(crate::load_ast("{\"statements\": ["), Err(Error::Parse(_))));
+    }
 }
@@ -0,0 +1,184 @@
+use crate::debugger::DebugHook;
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Statements longer than this are cut off in the trace.
+const MAX_RENDERED: usize = 80;
+
+/// Logs every statement, call and return as the interpreter runs them, for
+/// `bolt --trace`. Each line shows the source line, how many environments
+/// deep the interpreter is, and the call depth as indentation.
+pub struct Tracer {
+    output: Rc<RefCell<dyn Write>>,
+    /// Only calls to these functions (and everything they run) are traced.
+    /// Empty traces the whole script.
+    functions: BTreeSet<String>,
+    stack: Vec<String>,
+    /// The line each frame has reached, starting with the script's.
+    lines: Vec<usize>,
+}
+
+fn environment_depth(environment: &Rc<RefCell<Environment>>) -> usize {
+    let mut depth = 1;
+    let mut current = environment.borrow().enclosing.clone();
+    while let Some(env) = current {
+        depth += 1;
+        current = env.borrow().enclosing.clone();
+    }
+    depth
+}
+
+fn render(stmt: &Stmt) -> String {
+    let rendered = stmt.to_string();
+    match rendered.char_indices().nth(MAX_RENDERED) {
+        Some((cut, _)) => format!("{}...", &rendered[..cut]),
+        None => rendered,
+    }
+}
+
+impl Tracer {
+    pub fn new(output: Rc<RefCell<dyn Write>>, functions: &[String]) -> Self {
+        Self {
+            output,
+            functions: functions.iter().cloned().collect(),
+            stack: vec![],
+            lines: vec![0],
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.functions.is_empty() || self.stack.iter().any(|name| self.functions.contains(name))
+    }
+
+    fn log(&self, interpreter: &Interpreter, text: &str) -> Result<(), String> {
+        writeln!(
+            self.output.borrow_mut(),
+            "[line {}] env {} | {}{}",
+            self.lines.last().unwrap_or(&0),
+            environment_depth(&interpreter.environment),
+            "  ".repeat(self.stack.len()),
+            text
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl DebugHook for Tracer {
+    fn before_stmt(&mut self, stmt: &Stmt, interpreter: &Interpreter) -> Result<(), String> {
+        // A block's statements are traced one by one.
+        if let Stmt::Block { .. } = stmt {
+            return Ok(());
+        }
+        if let (Some(line), Some(current)) = (stmt.line(), self.lines.last_mut()) {
+            *current = line;
+        }
+        if self.enabled() {
+            self.log(interpreter, &render(stmt))?;
+        }
+        Ok(())
+    }
+
+    fn enter_function(
+        &mut self,
+        name: &str,
+        line: usize,
+        args: &[LiteralValue],
+        interpreter: &Interpreter,
+    ) -> Result<(), String> {
+        self.stack.push(name.to_string());
+        if self.enabled() {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            // Logged one level out, level with the call site.
+            self.stack.pop();
+            self.log(interpreter, &format!("-> {}({})", name, args.join(", ")))?;
+            self.stack.push(name.to_string());
+        }
+        let line = self.lines.last().copied().unwrap_or(0);
+        self.lines.push(line);
+        Ok(())
+    }
+
+    fn exit_function(&mut self, result: &Result<LiteralValue, String>, interpreter: &Interpreter) {
+        let enabled = self.enabled();
+        let Some(name) = self.stack.pop() else {
+            return;
+        };
+        if enabled {
+            let text = match result {
+                Ok(value) => format!("<- {} = {}", name, value.to_string()),
+                Err(msg) => format!("<- {} failed: {}", name, msg),
+            };
+            // A trace that can't be written shouldn't change how the call ends.
+            let _ = self.log(interpreter, &text);
+        }
+        if self.lines.len() > 1 {
+            self.lines.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_unoptimized;
+
+    fn trace(source: &str, functions: &[&str]) -> String {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let functions: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+        let tracer = Tracer::new(log.clone(), &functions);
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::with_output(output.clone(), output);
+        interpreter.set_debug_hook(Rc::new(RefCell::new(tracer)));
+        run_unoptimized(&mut interpreter, source).unwrap();
+
+        let log = log.borrow().clone();
+        String::from_utf8(log).unwrap()
+    }
+
+    const SOURCE: &str = "fun add(a, b) {\n  return a + b;\n}\nvar x = add(1, 2);\nif (x > 2) {\n  print x;\n}\n";
+
+    #[test]
+    fn logs_statements_and_calls() {
+        assert_eq!(
+            trace(SOURCE, &[]),
+            "\
+[line 1] env 1 | (fun add (a b) (return (+ (var a) (var b))))
+[line 4] env 1 | (var x (call (var add) 1 2))
+[line 4] env 2 | -> add(1, 2)
+[line 2] env 2 |   (return (+ (var a) (var b)))
+[line 2] env 2 | <- add = 3
+[line 5] env 1 | (if (> (var x) 2) (block (print (var x))))
+[line 6] env 2 | (print (var x))
+"
+        );
+    }
+
+    #[test]
+    fn filters_by_function_name() {
+        let source = "fun double(n) { return n * 2; }\nfun quad(n) { return double(double(n)); }\nprint double(1);\nprint quad(1);\n";
+
+        let log = trace(source, &["quad"]);
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines[0], "[line 4] env 2 | -> quad(1)");
+        assert_eq!(lines.last(), Some(&"[line 2] env 2 | <- quad = 4"));
+        assert_eq!(log.matches("-> double").count(), 2);
+    }
+
+    #[test]
+    fn logs_failed_calls() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debug_hook(Rc::new(RefCell::new(Tracer::new(log.clone(), &[]))));
+        assert!(run_unoptimized(&mut interpreter, "fun bad() { return 1 - \"a\"; }\nbad();\n").is_err());
+
+        let log = String::from_utf8(log.borrow().clone()).unwrap();
+        assert!(log.contains("<- bad failed: "), "{}", log);
+    }
+}
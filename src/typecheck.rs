@@ -0,0 +1,730 @@
+use crate::expr::{Arity, Expr, LiteralValue};
+use crate::scanner::{Token, TokenType};
+use crate::stmt::Stmt;
+use std::collections::{HashMap, HashSet};
+
+/// Static type of an expression. `Any` is what unannotated, dynamic code
+/// gets and is compatible with everything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Number,
+    String,
+    Boolean,
+    Nil,
+    Array,
+    Object,
+    Range,
+    /// A function, with its signature when it is known statically.
+    Function(Option<Box<Signature>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    /// Parameter names, for checking named arguments.
+    pub names: Vec<String>,
+    pub arity: Arity,
+    pub ret: Type,
+}
+
+impl Type {
+    /// Reads a type annotation such as the `Number` in `var x: Number`.
+    pub fn from_annotation(token: &Token) -> Result<Type, String> {
+        match token.lexeme.as_str() {
+            "Any" => Ok(Type::Any),
+            "Number" => Ok(Type::Number),
+            "String" => Ok(Type::String),
+            "Boolean" => Ok(Type::Boolean),
+            "Nil" => Ok(Type::Nil),
+            "Array" => Ok(Type::Array),
+            "Object" => Ok(Type::Object),
+            "Range" => Ok(Type::Range),
+            "Function" => Ok(Type::Function(None)),
+            other => Err(format!("Line {}: Unknown type '{}'", token.lineNumber, other)),
+        }
+    }
+
+    fn of_value(value: &LiteralValue) -> Type {
+        match value {
+            LiteralValue::Number(_) => Type::Number,
+            LiteralValue::StringValue(_) => Type::String,
+            LiteralValue::True | LiteralValue::False => Type::Boolean,
+            LiteralValue::Nil => Type::Nil,
+            LiteralValue::Callable { .. } => Type::Function(None),
+            LiteralValue::Array(_) => Type::Array,
+            LiteralValue::Object(_) => Type::Object,
+            LiteralValue::Range { .. } => Type::Range,
+        }
+    }
+
+    /// Whether a value of type `actual` may be stored where `self` is
+    /// expected. `nil` is accepted anywhere, like an uninitialized variable.
+    fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) | (_, Type::Nil) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        *self != Type::Any
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Any => "Any",
+            Type::Number => "Number",
+            Type::String => "String",
+            Type::Boolean => "Boolean",
+            Type::Nil => "Nil",
+            Type::Array => "Array",
+            Type::Object => "Object",
+            Type::Range => "Range",
+            Type::Function(_) => "Function",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Checks annotated code before it runs. Unannotated variables take the type
+/// of their initializer unless they are assigned somewhere else in the
+/// program or declared again as globals, in which case they are treated as
+/// dynamic.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Type>>,
+    reassigned: HashSet<String>,
+    return_types: Vec<Type>,
+    pub errors: Vec<String>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            reassigned: HashSet::new(),
+            return_types: vec![],
+            errors: vec![],
+        }
+    }
+
+    /// Checks a whole program and returns every mismatch found, each
+    /// prefixed with its line.
+    pub fn check_program(&mut self, stmts: &[Stmt]) -> Result<(), Vec<String>> {
+        let mut globals = HashSet::new();
+        for stmt in stmts {
+            collect_assigned_stmt(stmt, &mut self.reassigned);
+            // A global declared twice can change type between the two, and
+            // functions see whichever declaration ran last.
+            if let Stmt::Var { name, .. } | Stmt::Function { name, .. } = stmt {
+                if !globals.insert(name.lexeme.clone()) {
+                    self.reassigned.insert(name.lexeme.clone());
+                }
+            }
+        }
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    fn error(&mut self, line: usize, msg: String) {
+        self.errors.push(format!("Line {}: {}", line, msg));
+    }
+
+    fn annotation(&mut self, token: &Option<Token>) -> Type {
+        match token.as_ref().map(Type::from_annotation) {
+            Some(Ok(ty)) => ty,
+            Some(Err(msg)) => {
+                self.errors.push(msg);
+                Type::Any
+            }
+            None => Type::Any,
+        }
+    }
+
+    fn define(&mut self, name: &str, ty: Type) {
+        let ty = if self.reassigned.contains(name) && matches!(ty, Type::Function(Some(_))) {
+            Type::Function(None)
+        } else {
+            ty
+        };
+        self.scopes
+            .last_mut()
+            .expect("Type checker has no scope")
+            .insert(name.to_string(), ty);
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or(Type::Any)
+    }
+
+    fn check_block(&mut self, stmts: &[Box<Stmt>]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+                self.infer(expression);
+            }
+            Stmt::Var {
+                name,
+                type_annotation,
+                initializer,
+            } => {
+                let actual = self.infer(initializer);
+                let ty = match type_annotation {
+                    Some(_) => {
+                        let declared = self.annotation(type_annotation);
+                        if !declared.accepts(&actual) {
+                            self.error(
+                                name.lineNumber,
+                                format!(
+                                    "Can't initialize '{}' of type {} with {}",
+                                    name.lexeme, declared, actual
+                                ),
+                            );
+                        }
+                        declared
+                    }
+                    None if self.reassigned.contains(&name.lexeme) => Type::Any,
+                    None => actual,
+                };
+                self.define(&name.lexeme, ty);
+            }
+            Stmt::Block { statements } => self.check_block(statements),
+            Stmt::IfStmt {
+                predicate,
+                then,
+                els,
+                ..
+            } => {
+                self.infer(predicate);
+                self.check_stmt(then);
+                if let Some(els) = els {
+                    self.check_stmt(els);
+                }
+            }
+            Stmt::WhileStmt {
+                condition, body, ..
+            } => {
+                self.infer(condition);
+                self.check_stmt(body);
+            }
+            Stmt::ForIn {
+                keyword,
+                variable,
+                iterable,
+                body,
+            } => {
+                let item = match self.infer(iterable) {
+                    _ if self.reassigned.contains(&variable.lexeme) => Type::Any,
+                    Type::String => Type::String,
+                    Type::Range => Type::Number,
+                    Type::Any | Type::Array | Type::Object | Type::Function(_) => Type::Any,
+                    other => {
+                        self.error(keyword.lineNumber, format!("Can't iterate over {}", other));
+                        Type::Any
+                    }
+                };
+                self.scopes.push(HashMap::new());
+                self.define(&variable.lexeme, item);
+                self.check_stmt(body);
+                self.scopes.pop();
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                defaults,
+                rest,
+                return_type,
+                body,
+            } => {
+                let signature = self.signature(params, param_types, defaults, *rest, return_type);
+                self.define(&name.lexeme, Type::Function(Some(Box::new(signature.clone()))));
+                self.check_function(params, defaults, &signature, body);
+            }
+            Stmt::ReturnStmt { keyword, value } => {
+                let actual = match value {
+                    Some(value) => self.infer(value),
+                    None => Type::Nil,
+                };
+                if let Some(expected) = self.return_types.last().cloned() {
+                    if !expected.accepts(&actual) {
+                        self.error(
+                            keyword.lineNumber,
+                            format!("Expected return type {} but got {}", expected, actual),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn signature(
+        &mut self,
+        params: &[Token],
+        param_types: &[Option<Token>],
+        defaults: &[Option<Expr>],
+        rest: bool,
+        return_type: &Option<Token>,
+    ) -> Signature {
+        let last = param_types.len().saturating_sub(1);
+        Signature {
+            params: param_types
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| match ty {
+                    // An unannotated rest parameter still always holds an array.
+                    None if rest && i == last => Type::Array,
+                    ty => self.annotation(ty),
+                })
+                .collect(),
+            names: params.iter().map(|param| param.lexeme.clone()).collect(),
+            arity: Arity::of(defaults, rest),
+            ret: self.annotation(return_type),
+        }
+    }
+
+    fn check_function(
+        &mut self,
+        params: &[Token],
+        defaults: &[Option<Expr>],
+        signature: &Signature,
+        body: &[Box<Stmt>],
+    ) {
+        self.scopes.push(HashMap::new());
+        for ((param, ty), default) in params.iter().zip(&signature.params).zip(defaults) {
+            if let Some(default) = default {
+                let actual = self.infer(default);
+                if !ty.accepts(&actual) {
+                    self.error(
+                        param.lineNumber,
+                        format!(
+                            "Default of '{}' expects {} but got {}",
+                            param.lexeme, ty, actual
+                        ),
+                    );
+                }
+            }
+            self.define(&param.lexeme, ty.clone());
+        }
+        self.return_types.push(signature.ret.clone());
+        self.check_block(body);
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal { value } => Type::of_value(value),
+            Expr::Grouping { expression } => self.infer(expression),
+            Expr::Variable { name } => self.lookup(&name.lexeme),
+            Expr::Assign { name, value } => {
+                let actual = self.infer(value);
+                let declared = self.lookup(&name.lexeme);
+                if !declared.accepts(&actual) {
+                    self.error(
+                        name.lineNumber,
+                        format!(
+                            "Can't assign {} to '{}' of type {}",
+                            actual, name.lexeme, declared
+                        ),
+                    );
+                }
+                actual
+            }
+            Expr::Unary { operator, right } => {
+                let right = self.infer(right);
+                match operator.token_type {
+                    TokenType::Minus => {
+                        if right.is_known() && right != Type::Number {
+                            self.error(
+                                operator.lineNumber,
+                                format!("Minus not implemented for {}", right),
+                            );
+                        }
+                        Type::Number
+                    }
+                    _ => Type::Boolean,
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.infer(left);
+                let right = self.infer(right);
+                self.binary(operator, left, right)
+            }
+            Expr::Logical { left, right, .. } => {
+                let left = self.infer(left);
+                let right = self.infer(right);
+                if left == right {
+                    left
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                named,
+            } => {
+                let callee_type = self.infer(callee);
+                let args: Vec<Type> = arguments.iter().map(|arg| self.infer(arg)).collect();
+                let named: Vec<(&Token, Type)> = named
+                    .iter()
+                    .map(|(name, arg)| (name, self.infer(arg)))
+                    .collect();
+                let name = match callee.as_ref() {
+                    Expr::Variable { name } => name.lexeme.clone(),
+                    _ => "function".to_string(),
+                };
+                match callee_type {
+                    Type::Function(Some(signature)) => {
+                        self.check_call(&name, paren, &signature, &args, &named);
+                        signature.ret
+                    }
+                    Type::Function(None) | Type::Any => Type::Any,
+                    other => {
+                        self.error(
+                            paren.lineNumber,
+                            format!("Can only call functions, but '{}' is {}", name, other),
+                        );
+                        Type::Any
+                    }
+                }
+            }
+            Expr::AnonFunction {
+                arguments,
+                param_types,
+                defaults,
+                rest,
+                return_type,
+                body,
+                ..
+            } => {
+                let signature =
+                    self.signature(arguments, param_types, defaults, *rest, return_type);
+                self.check_function(arguments, defaults, &signature, body);
+                Type::Function(Some(Box::new(signature)))
+            }
+        }
+    }
+
+    fn check_call(
+        &mut self,
+        name: &str,
+        paren: &Token,
+        signature: &Signature,
+        args: &[Type],
+        named: &[(&Token, Type)],
+    ) {
+        let count = args.len() + named.len();
+        if !signature.arity.accepts(count) {
+            self.error(
+                paren.lineNumber,
+                format!(
+                    "'{}' expected {} arguments but got {}",
+                    name,
+                    signature.arity.to_string(),
+                    count
+                ),
+            );
+            return;
+        }
+        // Extra positional arguments go to the rest parameter unchecked.
+        let fixed = signature.params.len() - signature.arity.max.is_none() as usize;
+        for (i, (expected, actual)) in signature.params[..fixed].iter().zip(args).enumerate() {
+            if !expected.accepts(actual) {
+                self.error(
+                    paren.lineNumber,
+                    format!(
+                        "Argument {} of '{}' expects {} but got {}",
+                        i + 1,
+                        name,
+                        expected,
+                        actual
+                    ),
+                );
+            }
+        }
+        let mut passed: Vec<bool> = (0..fixed).map(|i| i < args.len()).collect();
+        for (arg_name, actual) in named {
+            let Some(i) = signature.names[..fixed]
+                .iter()
+                .position(|param| *param == arg_name.lexeme)
+            else {
+                self.error(
+                    arg_name.lineNumber,
+                    format!("'{}' has no parameter named '{}'", name, arg_name.lexeme),
+                );
+                continue;
+            };
+            if passed[i] {
+                self.error(
+                    arg_name.lineNumber,
+                    format!("'{}' got argument '{}' twice", name, arg_name.lexeme),
+                );
+            }
+            passed[i] = true;
+            if !signature.params[i].accepts(actual) {
+                self.error(
+                    arg_name.lineNumber,
+                    format!(
+                        "Argument '{}' of '{}' expects {} but got {}",
+                        arg_name.lexeme, name, signature.params[i], actual
+                    ),
+                );
+            }
+        }
+        for (param, passed) in signature.names[..signature.arity.min].iter().zip(passed) {
+            if !passed {
+                self.error(
+                    paren.lineNumber,
+                    format!("'{}' is missing argument '{}'", name, param),
+                );
+            }
+        }
+    }
+
+    /// Mirrors the rules of `Expr::evaluate`'s `Binary` arm for operands
+    /// whose types are known.
+    fn binary(&mut self, operator: &Token, left: Type, right: Type) -> Type {
+        use TokenType::*;
+
+        let op = operator.token_type;
+        let result = match op {
+            Plus if left == Type::String || right == Type::String => Type::String,
+            Plus if left == Type::Number || right == Type::Number => Type::Number,
+            Plus => Type::Any,
+            Minus | Star | Slash => Type::Number,
+            DotDot | DotDotEqual => Type::Range,
+            _ => Type::Boolean,
+        };
+        if !left.is_known() || !right.is_known() {
+            return result;
+        }
+
+        let allowed = match (&left, &right) {
+            (Type::Number, Type::String) | (Type::String, Type::Number) => {
+                self.error(
+                    operator.lineNumber,
+                    format!("{} is not defined string and number", op),
+                );
+                return result;
+            }
+            _ if matches!(op, EqualEqual | BangEqual) => true,
+            (Type::Number, Type::Number) => true,
+            (Type::String, Type::String) => {
+                !matches!(op, Minus | Star | Slash | DotDot | DotDotEqual)
+            }
+            _ => false,
+        };
+        if !allowed {
+            self.error(
+                operator.lineNumber,
+                format!("{} is not implemented for operands {} and {}", op, left, right),
+            );
+        }
+        result
+    }
+}
+
+fn collect_assigned_stmt(stmt: &Stmt, names: &mut HashSet<String>) {
+    match stmt {
+        Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+            collect_assigned_expr(expression, names)
+        }
+        Stmt::Var { initializer, .. } => collect_assigned_expr(initializer, names),
+        Stmt::Block { statements } | Stmt::Function { body: statements, .. } => {
+            for stmt in statements {
+                collect_assigned_stmt(stmt, names);
+            }
+        }
+        Stmt::IfStmt {
+            predicate,
+            then,
+            els,
+            ..
+        } => {
+            collect_assigned_expr(predicate, names);
+            collect_assigned_stmt(then, names);
+            if let Some(els) = els {
+                collect_assigned_stmt(els, names);
+            }
+        }
+        Stmt::WhileStmt {
+            condition, body, ..
+        } => {
+            collect_assigned_expr(condition, names);
+            collect_assigned_stmt(body, names);
+        }
+        Stmt::ForIn { iterable, body, .. } => {
+            collect_assigned_expr(iterable, names);
+            collect_assigned_stmt(body, names);
+        }
+        Stmt::ReturnStmt { value, .. } => {
+            if let Some(value) = value {
+                collect_assigned_expr(value, names);
+            }
+        }
+    }
+}
+
+fn collect_assigned_expr(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Assign { name, value } => {
+            names.insert(name.lexeme.clone());
+            collect_assigned_expr(value, names);
+        }
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            collect_assigned_expr(left, names);
+            collect_assigned_expr(right, names);
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            collect_assigned_expr(callee, names);
+            for arg in arguments {
+                collect_assigned_expr(arg, names);
+            }
+        }
+        Expr::Grouping { expression } => collect_assigned_expr(expression, names),
+        Expr::Unary { right, .. } => collect_assigned_expr(right, names),
+        Expr::AnonFunction { body, .. } => {
+            for stmt in body {
+                collect_assigned_stmt(stmt, names);
+            }
+        }
+        Expr::Literal { .. } | Expr::Variable { .. } => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn check(source: &str) -> Result<(), Vec<String>> {
+        let stmts = parse(source).unwrap();
+        TypeChecker::new().check_program(&stmts)
+    }
+
+    #[test]
+    fn accepts_well_typed_annotations() {
+        let source = "fun add(a: Number, b: Number): Number { return a + b; }\nvar x: Number = add(1, 2);\nvar s: String = \"n = \" + \"3\";\n";
+
+        assert_eq!(check(source), Ok(()));
+    }
+
+    #[test]
+    fn reports_mismatches_with_lines() {
+        let source = "var x: Number = \"one\";\nfun add(a: Number, b: Number): Number {\n  return \"sum\";\n}\nadd(1, true);\nprint \"a\" - 1;\n";
+
+        assert_eq!(
+            check(source),
+            Err(vec![
+                "Line 1: Can't initialize 'x' of type Number with String".to_string(),
+                "Line 3: Expected return type Number but got String".to_string(),
+                "Line 5: Argument 2 of 'add' expects Number but got Boolean".to_string(),
+                "Line 6: Minus is not defined string and number".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn infers_unannotated_locals() {
+        let source = "fun f() {\n  var s = \"text\";\n  return s * 2;\n}\n";
+
+        assert_eq!(
+            check(source),
+            Err(vec![
+                "Line 3: Star is not defined string and number".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn unannotated_code_is_dynamic() {
+        let source = "var x = 1;\nx = \"now a string\";\nprint x - 1;\nfun f(a) { return a * 2; }\nprint f(\"s\");\n";
+
+        assert_eq!(check(source), Ok(()));
+
+        let source = "var x = \"s\"; fun f() { return x * 2; } var x = 3; print f();";
+        assert_eq!(check(source), Ok(()));
+        let source = "fun g() { return 1; } var n: Number = g(); fun g() { return \"s\"; }";
+        assert_eq!(check(source), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unknown_type_names() {
+        assert_eq!(
+            check("var x: Integer = 1;"),
+            Err(vec!["Line 1: Unknown type 'Integer'".to_string()])
+        );
+    }
+
+    #[test]
+    fn checks_defaults_rest_and_named_arguments() {
+        let source = "fun f(a: Number, b: String = \"x\", ...rest) { return rest; }\nf(1);\nf(1, \"y\", true, nil);\nf(b: \"z\", a: 2);\nvar r: Array = f(1);\n";
+        assert_eq!(check(source), Ok(()));
+
+        let source = "fun f(a: Number, b: String = 1) {}\nf();\nf(b: 2, a: 1);\nf(c: 1, a: 1);\nf(\"s\", a: 1);\nf(b: \"s\");\n";
+        assert_eq!(
+            check(source),
+            Err(vec![
+                "Line 1: Default of 'b' expects String but got Number".to_string(),
+                "Line 2: 'f' expected 1..=2 arguments but got 0".to_string(),
+                "Line 3: Argument 'b' of 'f' expects String but got Number".to_string(),
+                "Line 4: 'f' has no parameter named 'c'".to_string(),
+                "Line 5: Argument 1 of 'f' expects Number but got String".to_string(),
+                "Line 5: 'f' got argument 'a' twice".to_string(),
+                "Line 6: 'f' is missing argument 'a'".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn checks_for_in_loops() {
+        let source = "var r: Range = 0..3;\nfor (i in r) { var n: Number = i; }\nfor (c in \"ab\") { var s: String = c; }\n";
+        assert_eq!(check(source), Ok(()));
+
+        let source = "for (c in \"ab\") { var n: Number = c; }\nfor (x in true) {}\nvar r = \"a\"..\"b\";\n";
+        assert_eq!(
+            check(source),
+            Err(vec![
+                "Line 1: Can't initialize 'n' of type Number with String".to_string(),
+                "Line 2: Can't iterate over Boolean".to_string(),
+                "Line 3: DotDot is not implemented for operands String and String".to_string(),
+            ])
+        );
+    }
+}
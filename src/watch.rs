@@ -0,0 +1,151 @@
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::parse;
+use crate::stmt::Stmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often `bolt run --watch` checks the script for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a [`reload`] changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Reload {
+    /// Functions that were (re)defined.
+    pub functions: Vec<String>,
+    /// Globals that didn't exist before and were initialized.
+    pub variables: Vec<String>,
+}
+
+impl Reload {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} function(s) replaced, {} variable(s) added",
+            self.functions.len(),
+            self.variables.len()
+        )
+    }
+}
+
+/// Re-runs the top-level definitions of `source` in `interpreter`. Every
+/// `fun` declaration replaces the function of that name, and `var`
+/// declarations only run for globals that don't exist yet, so data from
+/// earlier runs is kept. Other statements are skipped. Nothing runs if the
+/// source doesn't parse.
+pub fn reload(interpreter: &mut Interpreter, source: &str) -> Result<Reload, Error> {
+    let stmts = parse(source)?;
+    let mut reload = Reload::default();
+    let mut definitions = vec![];
+    for stmt in &stmts {
+        match stmt {
+            Stmt::Function { name, .. } => reload.functions.push(name.lexeme.clone()),
+            Stmt::Var { name, .. } if interpreter.get_global(&name.lexeme).is_none() => {
+                reload.variables.push(name.lexeme.clone())
+            }
+            _ => continue,
+        }
+        definitions.push(stmt);
+    }
+    interpreter.execute(definitions)?;
+    Ok(reload)
+}
+
+/// Polls `path` and calls `on_change` with its new contents whenever they
+/// change. A file that is briefly missing (as when an editor replaces it on
+/// save) is waited for. Only returns if `on_change` fails.
+pub fn watch<F>(path: &Path, mut on_change: F) -> Result<(), String>
+where
+    F: FnMut(&str) -> Result<(), String>,
+{
+    let mut modified = modified_time(path);
+    let mut contents = fs::read_to_string(path).ok();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let now = modified_time(path);
+        if now.is_none() || now == modified {
+            continue;
+        }
+        modified = now;
+        let Ok(new_contents) = fs::read_to_string(path) else {
+            continue;
+        };
+        if contents.as_ref() != Some(&new_contents) {
+            on_change(&new_contents)?;
+            contents = Some(new_contents);
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::LiteralValue;
+    use crate::run;
+
+    const FIRST: &str =
+        "var count = 0;\nfun step() { count = count + 1; return count; }\nstep();\n";
+
+    #[test]
+    fn replaces_functions_and_keeps_data() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, FIRST).unwrap();
+
+        let second = "var count = 100;\nvar label = \"steps\";\nfun step() { count = count + 10; return count; }\nstep();\n";
+        let reload = reload(&mut interpreter, second).unwrap();
+
+        assert_eq!(
+            reload,
+            Reload {
+                functions: vec!["step".to_string()],
+                variables: vec!["label".to_string()],
+            }
+        );
+        // `step();` isn't re-run and `count` keeps its value.
+        assert_eq!(
+            interpreter.get_global("count"),
+            Some(LiteralValue::Number(1.0))
+        );
+        assert_eq!(
+            interpreter.call_fn("step", vec![]),
+            Ok(LiteralValue::Number(11.0))
+        );
+    }
+
+    #[test]
+    fn parse_errors_leave_the_interpreter_alone() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, FIRST).unwrap();
+
+        let broken = "fun step() { return 99; }\nfun oops( {\n";
+        assert!(matches!(
+            reload(&mut interpreter, broken),
+            Err(Error::Parse(_))
+        ));
+        assert_eq!(
+            interpreter.call_fn("step", vec![]),
+            Ok(LiteralValue::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn other_functions_see_the_replacement() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun greet() { return \"hi\"; }\nfun main() { return greet(); }\n",
+        )
+        .unwrap();
+        reload(&mut interpreter, "fun greet() { return \"hello\"; }\n").unwrap();
+
+        assert_eq!(
+            interpreter.call_fn("main", vec![]),
+            Ok(LiteralValue::StringValue("hello".to_string()))
+        );
+    }
+}